@@ -4,9 +4,17 @@
  * @copyright 2023 - 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
+//! Contracts of the Lambda handlers and downstream services.
+
+use crate::diff::{ClaimsDiff, LicenseChange};
+use crate::filter::LicenseSelection;
+use crate::model::{Audience, ClaimsVersion, ExpiryFormat};
 use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
 use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use thiserror::Error;
 use uuid::Uuid;
 
@@ -17,9 +25,43 @@ use uuid::Uuid;
 pub struct GeneratorRequest {
     pub customer_id: Uuid,
     pub vessel_id: Uuid,
-    pub inventory_key: String,
+    /// `jwt_key` inventory entry holding the signing key, defaults to the minion's own entry.
+    #[serde(default)]
+    pub inventory_key: Option<String>,
+    /// Salt minion the token is bound to.
+    #[serde(default)]
+    pub minion_id: Option<String>,
     pub issuer: String,
-    pub audience: String,
+    /// Either a single audience or a list of them.
+    pub audience: Audience,
+    #[serde(default)]
+    pub bypass_cache: bool,
+    #[serde(default)]
+    pub license_expiry: ExpiryFormat,
+    /// Forces claims layout for verifiers that don't support the one implied by `license_expiry`.
+    #[serde(default)]
+    pub claims_version: Option<ClaimsVersion>,
+    #[serde(flatten)]
+    pub selection: LicenseSelection,
+    /// Inventory entries embedded in `ivms:inventory` claim.
+    #[serde(default)]
+    pub inventory: Vec<InventoryReference>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryReference {
+    pub inventory_type: String,
+    pub inventory_id: String,
+}
+
+impl GeneratorRequest {
+    pub fn key_inventory_id(&self) -> Result<&str, RuntimeError> {
+        self.inventory_key
+            .as_deref()
+            .or(self.minion_id.as_deref())
+            .ok_or(RuntimeError::MissingInventoryKey)
+    }
 }
 
 #[derive(Serialize)]
@@ -34,6 +76,55 @@ impl GeneratorResponse {
     }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffRequest {
+    pub customer_id: Uuid,
+    pub vessel_id: Uuid,
+    pub token: String,
+    /// Same selection as used for generating the deployed token.
+    #[serde(flatten)]
+    pub selection: LicenseSelection,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffResponse {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<LicenseChange>,
+    pub expired: bool,
+    pub needs_refresh: bool,
+}
+
+impl DiffResponse {
+    pub fn new(diff: ClaimsDiff, expired: bool) -> Self {
+        Self {
+            needs_refresh: expired || !diff.is_empty(),
+            added: diff.added,
+            removed: diff.removed,
+            changed: diff.changed,
+            expired,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenewRequest {
+    pub token: String,
+    /// `jwt_key` inventory entry holding the signing key, defaults to the minion the token is bound to.
+    #[serde(default)]
+    pub inventory_key: Option<String>,
+    /// Same selection as used for generating the token being renewed.
+    #[serde(flatten)]
+    pub selection: LicenseSelection,
+}
+
+/// Listing service keys takes no parameters.
+#[derive(Deserialize)]
+pub struct JwksRequest {}
+
 // downstream services API
 
 #[derive(Serialize)]
@@ -43,6 +134,9 @@ pub struct InventoryFetchRequest {
     pub vessel_id: Uuid,
     pub inventory_type: String,
     pub inventory_id: String,
+    /// W3C trace context (`traceparent`, `tracestate`) of the invocation span.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub trace_context: HashMap<String, String>,
 }
 
 #[derive(Deserialize)]
@@ -50,7 +144,7 @@ pub struct InventoryFetchRequest {
 pub struct InventoryFetchResponse {
     pub inventory_type: String,
     pub inventory_id: String,
-    pub serial_number: Option<String>,
+    pub serial_number: Option<SecretKey>,
     pub aws_instance_id: Option<String>,
     pub created_at: DateTime<FixedOffset>,
 }
@@ -61,14 +155,20 @@ pub struct LicensesListRequest {
     pub customer_id: Uuid,
     pub vessel_id: Uuid,
     pub page_token: Option<String>,
+    /// W3C trace context (`traceparent`, `tracestate`) of the invocation span.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub trace_context: HashMap<String, String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LicenseFetchResponse {
     pub license_key: String,
     pub count: Option<u8>,
     pub expires_at: Option<DateTime<FixedOffset>>,
+    /// Any other license parameters (tier, features, limits etc.).
+    #[serde(flatten)]
+    pub attributes: BTreeMap<String, Value>,
 }
 
 #[derive(Deserialize)]
@@ -94,22 +194,28 @@ impl From<RuntimeError> for ApiError {
 
 #[cfg(test)]
 mod tests {
-    use crate::api::{ApiError, GeneratorRequest, GeneratorResponse};
+    use crate::api::{
+        ApiError, DiffRequest, DiffResponse, GeneratorRequest, GeneratorResponse, InventoryReference,
+        LicenseFetchResponse, LicensesListRequest,
+    };
+    use crate::diff::ClaimsDiff;
+    use crate::model::{Audience, ClaimsVersion, ExpiryFormat};
     use crate::runtime_error::RuntimeError;
-    use serde_json::{from_str, to_string};
-    use std::env::VarError;
+    use serde_json::{from_str, json, to_string};
+    use std::collections::HashMap;
     use uuid::{uuid, Uuid};
 
     const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
     const VESSEL_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000001");
     const TOKEN: &str = "test0";
     const INVENTORY_KEY: &str = "local";
+    const MINION_ID: &str = "vessel-node-1";
     const ISSUER: &str = "unit-test";
     const AUDIENCE: &str = "local";
 
     #[test]
     fn runtime_api_error() {
-        match ApiError::from(RuntimeError::ClientConfigLoadingError(VarError::NotPresent)) {
+        match ApiError::from(RuntimeError::MissingKey) {
             ApiError::RuntimeError(_) => {}
         }
     }
@@ -121,7 +227,7 @@ mod tests {
         })
         .unwrap();
 
-        assert!(output.contains(&format!("test0")));
+        assert!(output.contains(TOKEN));
     }
 
     #[test]
@@ -132,6 +238,159 @@ mod tests {
         assert_eq!(CUSTOMER_ID, request.customer_id);
         assert_eq!(VESSEL_ID, request.vessel_id);
         assert_eq!(ISSUER.to_string(), request.issuer);
-        assert_eq!(AUDIENCE.to_string(), request.audience);
+        assert_eq!(Audience::from(AUDIENCE), request.audience);
+        assert!(!request.bypass_cache);
+        assert_eq!(ExpiryFormat::Original, request.license_expiry);
+        assert!(request.claims_version.is_none());
+        assert!(request.minion_id.is_none());
+        assert!(request.selection.role.is_none());
+        assert!(request.selection.license_patterns.is_none());
+        assert!(request.inventory.is_empty());
+        assert_eq!(INVENTORY_KEY, request.key_inventory_id().unwrap());
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_expiry_format() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\",\"licenseExpiry\":\"timestamp\"}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(ExpiryFormat::Timestamp, request.license_expiry);
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_multiple_audiences() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":[\"{AUDIENCE}\",\"salt\"]}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(
+            Audience::Multiple(vec![AUDIENCE.to_string(), "salt".to_string()]),
+            request.audience
+        );
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_minion_key() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"minionId\":\"{MINION_ID}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\"}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(Some(MINION_ID.to_string()), request.minion_id);
+        assert_eq!(MINION_ID, request.key_inventory_id().unwrap());
+    }
+
+    #[test]
+    fn deserialize_trigger_request_without_key() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\"}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert!(matches!(
+            request.key_inventory_id(),
+            Err(RuntimeError::MissingInventoryKey)
+        ));
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_license_selection() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\",\"role\":\"bridge\",\"licensePatterns\":[\"weather*\"]}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(Some("bridge".to_string()), request.selection.role);
+        assert_eq!(Some(vec!["weather*".to_string()]), request.selection.license_patterns);
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_inventory() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\",\"inventory\":[{{\"inventoryType\":\"gps\",\"inventoryId\":\"main\"}}]}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(
+            vec![InventoryReference {
+                inventory_type: "gps".to_string(),
+                inventory_id: "main".to_string(),
+            }],
+            request.inventory
+        );
+    }
+
+    #[test]
+    fn deserialize_trigger_request_bypassing_cache() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\",\"bypassCache\":true}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert!(request.bypass_cache);
+    }
+
+    #[test]
+    fn deserialize_trigger_request_with_claims_version() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"inventoryKey\":\"{INVENTORY_KEY}\",\"issuer\":\"{ISSUER}\",\"audience\":\"{AUDIENCE}\",\"claimsVersion\":1}}");
+        let request: GeneratorRequest = from_str(&input).unwrap();
+
+        assert_eq!(Some(ClaimsVersion::V1), request.claims_version);
+    }
+
+    #[test]
+    fn deserialize_diff_request() {
+        let input = format!("{{\"customerId\":\"{CUSTOMER_ID}\",\"vesselId\":\"{VESSEL_ID}\",\"token\":\"{TOKEN}\"}}");
+        let request: DiffRequest = from_str(&input).unwrap();
+
+        assert_eq!(CUSTOMER_ID, request.customer_id);
+        assert_eq!(VESSEL_ID, request.vessel_id);
+        assert_eq!(TOKEN.to_string(), request.token);
+    }
+
+    #[test]
+    fn serialize_diff_response() {
+        let output = to_string(&DiffResponse::new(
+            ClaimsDiff {
+                added: vec![String::from("foo")],
+                removed: vec![],
+                changed: vec![],
+            },
+            false,
+        ))
+        .unwrap();
+
+        assert!(output.contains("\"added\":[\"foo\"]"));
+        assert!(output.contains("\"needsRefresh\":true"));
+    }
+
+    #[test]
+    fn deserialize_license_attributes() {
+        let license: LicenseFetchResponse = from_str(
+            "{\"licenseKey\":\"foo\",\"count\":2,\"expiresAt\":null,\"tier\":\"gold\",\"limits\":{\"users\":5}}",
+        )
+        .unwrap();
+
+        assert_eq!("foo", license.license_key);
+        assert_eq!(Some(2), license.count);
+        assert_eq!(2, license.attributes.len());
+        assert_eq!(Some(&json!("gold")), license.attributes.get("tier"));
+        assert_eq!(Some(&json!({"users": 5})), license.attributes.get("limits"));
+    }
+
+    #[test]
+    fn serialize_licenses_request_with_trace_context() {
+        let mut request = LicensesListRequest {
+            customer_id: CUSTOMER_ID,
+            vessel_id: VESSEL_ID,
+            page_token: None,
+            trace_context: HashMap::new(),
+        };
+
+        assert!(!to_string(&request).unwrap().contains("traceContext"));
+
+        request.trace_context.insert(
+            "traceparent".to_string(),
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01".to_string(),
+        );
+
+        assert!(to_string(&request).unwrap().contains(
+            "\"traceContext\":{\"traceparent\":\"00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01\"}"
+        ));
+    }
+
+    #[test]
+    fn diff_response_up_to_date() {
+        assert!(!DiffResponse::new(ClaimsDiff::default(), false).needs_refresh);
+        assert!(DiffResponse::new(ClaimsDiff::default(), true).needs_refresh);
     }
 }
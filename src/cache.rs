@@ -0,0 +1,129 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! In-memory cache of downstream responses.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// warm Lambda containers keep the state between invocations, so this is enough to share results across them
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: RefCell<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.entries
+            .borrow()
+            .get(key)
+            .filter(|(stored_at, _)| stored_at.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        if self.is_enabled() {
+            let mut entries = self.entries.borrow_mut();
+
+            entries.retain(|_, (stored_at, _)| stored_at.elapsed() < self.ttl);
+            entries.insert(key, (Instant::now(), value));
+        }
+    }
+
+    // bypassing only skips the lookup - fresh result still replaces the stored one
+    pub async fn get_or_load<E, F, Fut>(&self, key: K, bypass: bool, loader: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<V, E>>,
+    {
+        if !bypass {
+            if let Some(value) = self.get(&key) {
+                return Ok(value);
+            }
+        }
+
+        let value = loader().await?;
+        self.insert(key, value.clone());
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::TtlCache;
+    use futures::executor::block_on;
+    use futures::future::{ready, Ready};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    const KEY: &str = "foo";
+
+    fn load(value: u8) -> impl FnOnce() -> Ready<Result<u8, ()>> {
+        move || ready(Ok(value))
+    }
+
+    #[test]
+    fn disabled_cache() {
+        let cache = TtlCache::new(Duration::ZERO);
+
+        assert!(!cache.is_enabled());
+        assert_eq!(Ok(1), block_on(cache.get_or_load(KEY, false, load(1))));
+        assert_eq!(Ok(2), block_on(cache.get_or_load(KEY, false, load(2))));
+        assert!(cache.get(&KEY).is_none());
+    }
+
+    #[test]
+    fn cached_value() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+
+        assert!(cache.is_enabled());
+        assert_eq!(Ok(1), block_on(cache.get_or_load(KEY, false, load(1))));
+        assert_eq!(Ok(1), block_on(cache.get_or_load(KEY, false, load(2))));
+    }
+
+    #[test]
+    fn bypassed_cache() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+
+        assert_eq!(Ok(1), block_on(cache.get_or_load(KEY, false, load(1))));
+        assert_eq!(Ok(2), block_on(cache.get_or_load(KEY, true, load(2))));
+        assert_eq!(Some(2), cache.get(&KEY));
+    }
+
+    #[test]
+    fn expired_value() {
+        let cache = TtlCache::new(Duration::from_millis(10));
+
+        cache.insert(KEY, 1);
+        sleep(Duration::from_millis(20));
+
+        assert!(cache.get(&KEY).is_none());
+        assert_eq!(Ok(2), block_on(cache.get_or_load(KEY, false, load(2))));
+    }
+
+    #[test]
+    fn failed_load_not_cached() {
+        let cache = TtlCache::<&str, u8>::new(Duration::from_secs(60));
+
+        assert_eq!(Err(()), block_on(cache.get_or_load(KEY, false, || ready(Err(())))));
+        assert!(cache.get(&KEY).is_none());
+    }
+}
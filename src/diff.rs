@@ -0,0 +1,202 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Comparison of license claims.
+
+use crate::model::LicenseClaim;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseChange {
+    pub license_key: String,
+    pub previous: LicenseClaim,
+    pub current: LicenseClaim,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimsDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<LicenseChange>,
+}
+
+impl ClaimsDiff {
+    pub fn between(deployed: &HashMap<String, LicenseClaim>, fresh: &HashMap<String, LicenseClaim>) -> Self {
+        let mut diff = Self::default();
+
+        for (license_key, current) in fresh {
+            match deployed.get(license_key) {
+                None => diff.added.push(license_key.clone()),
+                // expiration dates are compared as instants, so offset-only differences are not a change
+                Some(previous) if previous != current => diff.changed.push(LicenseChange {
+                    license_key: license_key.clone(),
+                    previous: previous.clone(),
+                    current: current.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+
+        diff.removed = deployed
+            .keys()
+            .filter(|license_key| !fresh.contains_key(*license_key))
+            .cloned()
+            .collect();
+
+        // keep output stable regardless of hash map ordering
+        diff.added.sort();
+        diff.removed.sort();
+        diff.changed
+            .sort_by(|left, right| left.license_key.cmp(&right.license_key));
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::ClaimsDiff;
+    use crate::model::{LicenseClaim, LicenseExpiry};
+    use chrono::{FixedOffset, TimeZone, Utc};
+    use std::collections::{BTreeMap, HashMap};
+
+    const LICENSE_KEY_0: &str = "foo";
+    const LICENSE_KEY_1: &str = "bar";
+    const LICENSE_KEY_2: &str = "baz";
+    const LICENSE_KEY_3: &str = "qux";
+
+    fn expires_at(offset: i32) -> LicenseExpiry {
+        Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0)
+            .unwrap()
+            .with_timezone(&FixedOffset::east_opt(offset).unwrap())
+            .into()
+    }
+
+    #[test]
+    fn diff_identical_licenses() {
+        let licenses = HashMap::from([(
+            LICENSE_KEY_0.to_string(),
+            LicenseClaim {
+                count: Some(2),
+                expires_at: Some(expires_at(3600)),
+                attributes: BTreeMap::new(),
+            },
+        )]);
+
+        let diff = ClaimsDiff::between(&licenses, &licenses.clone());
+
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn diff_ignores_timezone_offset() {
+        let deployed = HashMap::from([(
+            LICENSE_KEY_0.to_string(),
+            LicenseClaim {
+                count: None,
+                expires_at: Some(expires_at(3600)),
+                attributes: BTreeMap::new(),
+            },
+        )]);
+        let fresh = HashMap::from([(
+            LICENSE_KEY_0.to_string(),
+            LicenseClaim {
+                count: None,
+                expires_at: Some(expires_at(7200)),
+                attributes: BTreeMap::new(),
+            },
+        )]);
+
+        assert!(ClaimsDiff::between(&deployed, &fresh).is_empty());
+    }
+
+    #[test]
+    fn diff_ignores_expiry_format() {
+        let deployed = HashMap::from([(
+            LICENSE_KEY_0.to_string(),
+            LicenseClaim {
+                count: None,
+                expires_at: Some(expires_at(3600)),
+                attributes: BTreeMap::new(),
+            },
+        )]);
+        let fresh = HashMap::from([(
+            LICENSE_KEY_0.to_string(),
+            LicenseClaim {
+                count: None,
+                expires_at: Some(LicenseExpiry::Timestamp(1296395880)),
+                attributes: BTreeMap::new(),
+            },
+        )]);
+
+        assert!(ClaimsDiff::between(&deployed, &fresh).is_empty());
+    }
+
+    #[test]
+    fn diff_changed_licenses() {
+        let unchanged = LicenseClaim {
+            count: Some(1),
+            expires_at: None,
+            attributes: BTreeMap::new(),
+        };
+        let deployed = HashMap::from([
+            (LICENSE_KEY_0.to_string(), unchanged.clone()),
+            (
+                LICENSE_KEY_1.to_string(),
+                LicenseClaim {
+                    count: Some(2),
+                    expires_at: None,
+                    attributes: BTreeMap::new(),
+                },
+            ),
+            (
+                LICENSE_KEY_2.to_string(),
+                LicenseClaim {
+                    count: None,
+                    expires_at: None,
+                    attributes: BTreeMap::new(),
+                },
+            ),
+        ]);
+        let fresh = HashMap::from([
+            (LICENSE_KEY_0.to_string(), unchanged),
+            (
+                LICENSE_KEY_1.to_string(),
+                LicenseClaim {
+                    count: Some(4),
+                    expires_at: Some(expires_at(0)),
+                    attributes: BTreeMap::new(),
+                },
+            ),
+            (
+                LICENSE_KEY_3.to_string(),
+                LicenseClaim {
+                    count: None,
+                    expires_at: None,
+                    attributes: BTreeMap::new(),
+                },
+            ),
+        ]);
+
+        let diff = ClaimsDiff::between(&deployed, &fresh);
+
+        assert!(!diff.is_empty());
+        assert_eq!(vec![LICENSE_KEY_3.to_string()], diff.added);
+        assert_eq!(vec![LICENSE_KEY_2.to_string()], diff.removed);
+        assert_eq!(1, diff.changed.len());
+        assert_eq!(LICENSE_KEY_1, diff.changed[0].license_key);
+        assert_eq!(Some(2), diff.changed[0].previous.count);
+        assert_eq!(Some(4), diff.changed[0].current.count);
+        assert_eq!(Some(expires_at(0)), diff.changed[0].current.expires_at);
+    }
+}
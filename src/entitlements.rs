@@ -0,0 +1,244 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Entitlement queries over `ivms:licenses` claim.
+
+use crate::model::{Claims, LicenseClaim};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+
+/// Number of seats granted by a license.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Seats {
+    Limited(u8),
+    Unlimited,
+}
+
+/// Validity period of a license.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Validity {
+    Until(DateTime<Utc>),
+    Perpetual,
+}
+
+impl LicenseClaim {
+    pub fn seats(&self) -> Seats {
+        self.count.map_or(Seats::Unlimited, Seats::Limited)
+    }
+
+    pub fn validity(&self) -> Validity {
+        self.expires_at
+            .map_or(Validity::Perpetual, |expires_at| Validity::Until(expires_at.to_utc()))
+    }
+
+    /// License is active when it's not expired yet and grants at least one seat.
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        let seats = self.seats() != Seats::Limited(0);
+
+        match self.validity() {
+            Validity::Perpetual => seats,
+            Validity::Until(expires_at) => seats && expires_at > at,
+        }
+    }
+}
+
+/// Read-only view answering "is feature X licensed, with how many seats, until when?".
+pub struct Entitlements<'a> {
+    licenses: &'a HashMap<String, LicenseClaim>,
+}
+
+impl Claims {
+    pub fn entitlements(&self) -> Entitlements<'_> {
+        Entitlements {
+            licenses: &self.licenses,
+        }
+    }
+}
+
+impl<'a> Entitlements<'a> {
+    pub fn is_licensed(&self, license_key: &str, at: DateTime<Utc>) -> bool {
+        self.licenses
+            .get(license_key)
+            .is_some_and(|license| license.is_active_at(at))
+    }
+
+    /// `None` if the license is not granted at all.
+    pub fn seats(&self, license_key: &str) -> Option<Seats> {
+        self.licenses.get(license_key).map(LicenseClaim::seats)
+    }
+
+    /// `None` if the license is not granted at all.
+    pub fn validity(&self, license_key: &str) -> Option<Validity> {
+        self.licenses.get(license_key).map(LicenseClaim::validity)
+    }
+
+    pub fn expiring_within(&self, duration: Duration) -> Vec<&'a str> {
+        self.expiring_within_at(duration, Utc::now())
+    }
+
+    /// Keys of still active licenses that expire within given period, sorted by expiration date.
+    pub fn expiring_within_at(&self, duration: Duration, now: DateTime<Utc>) -> Vec<&'a str> {
+        // period reaching beyond representable dates covers every expiration date
+        let until = now.checked_add_signed(duration);
+        let mut expiring = self
+            .licenses
+            .iter()
+            .filter_map(|(license_key, license)| match license.validity() {
+                Validity::Until(expires_at) if expires_at > now && until.is_none_or(|until| expires_at <= until) => {
+                    Some((expires_at, license_key.as_str()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        expiring.sort();
+
+        expiring.into_iter().map(|(_, license_key)| license_key).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entitlements::{Seats, Validity};
+    use crate::model::{Claims, ClaimsVersion, LicenseClaim, LicenseExpiry};
+    use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+    use std::collections::{BTreeMap, HashMap};
+
+    const LIMITED: &str = "foo";
+    const UNLIMITED: &str = "bar";
+    const EXPIRED: &str = "baz";
+    const EXPIRING: &str = "qux";
+    const NO_SEATS: &str = "quux";
+    const MISSING: &str = "corge";
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0).unwrap()
+    }
+
+    fn days(days: i64) -> Duration {
+        Duration::try_days(days).unwrap()
+    }
+
+    fn at(offset: Duration) -> LicenseExpiry {
+        (now() + offset)
+            .with_timezone(&FixedOffset::east_opt(3600).unwrap())
+            .into()
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            issuer: "unit-test".to_string(),
+            user: "foo:bar".to_string(),
+            minion_id: None,
+            audience: "local".into(),
+            expires_at: 0,
+            issued_at: 0,
+            licenses: HashMap::from([
+                (
+                    LIMITED.to_string(),
+                    LicenseClaim {
+                        count: Some(12),
+                        expires_at: Some(at(days(365))),
+                        attributes: BTreeMap::new(),
+                    },
+                ),
+                (
+                    UNLIMITED.to_string(),
+                    LicenseClaim {
+                        count: None,
+                        expires_at: None,
+                        attributes: BTreeMap::new(),
+                    },
+                ),
+                (
+                    EXPIRED.to_string(),
+                    LicenseClaim {
+                        count: Some(2),
+                        expires_at: Some(at(-days(1))),
+                        attributes: BTreeMap::new(),
+                    },
+                ),
+                (
+                    EXPIRING.to_string(),
+                    LicenseClaim {
+                        count: None,
+                        expires_at: Some(at(days(7))),
+                        attributes: BTreeMap::new(),
+                    },
+                ),
+                (
+                    NO_SEATS.to_string(),
+                    LicenseClaim {
+                        count: Some(0),
+                        expires_at: None,
+                        attributes: BTreeMap::new(),
+                    },
+                ),
+            ]),
+            inventory: vec![],
+            version: ClaimsVersion::V1,
+        }
+    }
+
+    #[test]
+    fn is_licensed() {
+        let claims = claims();
+        let entitlements = claims.entitlements();
+
+        assert!(entitlements.is_licensed(LIMITED, now()));
+        assert!(entitlements.is_licensed(UNLIMITED, now()));
+        assert!(entitlements.is_licensed(EXPIRING, now()));
+        assert!(!entitlements.is_licensed(EXPIRED, now()));
+        assert!(!entitlements.is_licensed(NO_SEATS, now()));
+        assert!(!entitlements.is_licensed(MISSING, now()));
+        assert!(!entitlements.is_licensed(EXPIRING, now() + days(7)));
+    }
+
+    #[test]
+    fn seats() {
+        let claims = claims();
+        let entitlements = claims.entitlements();
+
+        assert_eq!(Some(Seats::Limited(12)), entitlements.seats(LIMITED));
+        assert_eq!(Some(Seats::Unlimited), entitlements.seats(UNLIMITED));
+        assert_eq!(Some(Seats::Limited(0)), entitlements.seats(NO_SEATS));
+        assert_eq!(None, entitlements.seats(MISSING));
+    }
+
+    #[test]
+    fn validity() {
+        let claims = claims();
+        let entitlements = claims.entitlements();
+
+        assert_eq!(Some(Validity::Until(now() + days(365))), entitlements.validity(LIMITED));
+        assert_eq!(Some(Validity::Perpetual), entitlements.validity(UNLIMITED));
+        assert_eq!(None, entitlements.validity(MISSING));
+    }
+
+    #[test]
+    fn expiring_within() {
+        let claims = claims();
+        let entitlements = claims.entitlements();
+
+        assert_eq!(vec![EXPIRING], entitlements.expiring_within_at(days(30), now()));
+        assert_eq!(
+            vec![EXPIRING, LIMITED],
+            entitlements.expiring_within_at(days(365), now())
+        );
+        assert!(entitlements.expiring_within_at(days(1), now()).is_empty());
+    }
+
+    #[test]
+    fn expiring_within_unbounded_period() {
+        let claims = claims();
+        let entitlements = claims.entitlements();
+
+        assert_eq!(
+            vec![EXPIRING, LIMITED],
+            entitlements.expiring_within_at(Duration::MAX, now())
+        );
+    }
+}
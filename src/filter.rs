@@ -0,0 +1,184 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Selection of licenses embedded in the token of a particular node.
+
+use crate::runtime_error::RuntimeError;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+
+/// Node role or explicit license key patterns, as specified in the request.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LicenseSelection {
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub license_patterns: Option<Vec<String>>,
+}
+
+/// License key patterns, where `*` matches any sequence of characters (eg. `weather*`), and allowlist of license
+/// attributes passed through to the claims.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LicenseFilter {
+    // `None` means all licenses
+    patterns: Option<Vec<String>>,
+    attributes: BTreeSet<String>,
+}
+
+impl LicenseFilter {
+    /// All licenses, without any attributes.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn patterns(patterns: Vec<String>) -> Self {
+        Self {
+            patterns: Some(patterns),
+            attributes: BTreeSet::new(),
+        }
+    }
+
+    pub fn with_attributes(mut self, attributes: impl IntoIterator<Item = String>) -> Self {
+        self.attributes.extend(attributes);
+        self
+    }
+
+    /// Resolves selection against roles definitions - role patterns and explicit patterns are combined.
+    pub fn resolve(selection: &LicenseSelection, roles: &HashMap<String, Vec<String>>) -> Result<Self, RuntimeError> {
+        let mut patterns = match &selection.role {
+            Some(role) => match roles.get(role) {
+                Some(patterns) => Some(patterns.clone()),
+                None => return Err(RuntimeError::UnknownRole(role.clone())),
+            },
+            None => None,
+        };
+
+        if let Some(explicit) = &selection.license_patterns {
+            patterns.get_or_insert_with(Vec::new).extend(explicit.iter().cloned());
+        }
+
+        Ok(Self {
+            patterns,
+            attributes: BTreeSet::new(),
+        })
+    }
+
+    pub fn allows_attribute(&self, name: &str) -> bool {
+        self.attributes.contains(name)
+    }
+
+    pub fn matches(&self, license_key: &str) -> bool {
+        match &self.patterns {
+            Some(patterns) => patterns.iter().any(|pattern| glob_match(pattern, license_key)),
+            None => true,
+        }
+    }
+}
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // split always yields at least one part
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts = parts.collect::<Vec<_>>();
+    let Some(last) = parts.pop() else {
+        // no wildcard at all
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{LicenseFilter, LicenseSelection};
+    use crate::runtime_error::RuntimeError;
+    use std::collections::HashMap;
+
+    fn roles() -> HashMap<String, Vec<String>> {
+        HashMap::from([("bridge".to_string(), vec!["weather*".to_string(), "ecdis".to_string()])])
+    }
+
+    #[test]
+    fn match_all() {
+        assert!(LicenseFilter::all().matches("anything"));
+    }
+
+    #[test]
+    fn match_patterns() {
+        let filter = LicenseFilter::patterns(vec![
+            "weather*".to_string(),
+            "*-engine".to_string(),
+            "crew*list*".to_string(),
+            "ecdis".to_string(),
+        ]);
+
+        assert!(filter.matches("weather"));
+        assert!(filter.matches("weather-routing"));
+        assert!(filter.matches("main-engine"));
+        assert!(filter.matches("crew-list"));
+        assert!(filter.matches("crew-watch-list-v2"));
+        assert!(filter.matches("ecdis"));
+        assert!(!filter.matches("ecdis-backup"));
+        assert!(!filter.matches("engine-room"));
+        assert!(!filter.matches("cargo"));
+    }
+
+    #[test]
+    fn allow_attributes() {
+        let filter = LicenseFilter::all().with_attributes(["tier".to_string(), "features".to_string()]);
+
+        assert!(filter.allows_attribute("tier"));
+        assert!(filter.allows_attribute("features"));
+        assert!(!filter.allows_attribute("price"));
+        assert!(!LicenseFilter::all().allows_attribute("tier"));
+    }
+
+    #[test]
+    fn resolve_role() {
+        let filter = LicenseFilter::resolve(
+            &LicenseSelection {
+                role: Some("bridge".to_string()),
+                license_patterns: Some(vec!["cargo".to_string()]),
+            },
+            &roles(),
+        )
+        .unwrap();
+
+        assert!(filter.matches("weather-routing"));
+        assert!(filter.matches("ecdis"));
+        assert!(filter.matches("cargo"));
+        assert!(!filter.matches("crew-list"));
+        assert_eq!(
+            LicenseFilter::all(),
+            LicenseFilter::resolve(&LicenseSelection::default(), &roles()).unwrap()
+        );
+    }
+
+    #[test]
+    fn resolve_unknown_role() {
+        let selection = LicenseSelection {
+            role: Some("galley".to_string()),
+            license_patterns: None,
+        };
+
+        assert!(matches!(
+            LicenseFilter::resolve(&selection, &roles()),
+            Err(RuntimeError::UnknownRole(_))
+        ));
+    }
+}
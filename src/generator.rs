@@ -4,104 +4,330 @@
  * @copyright 2023 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
+//! Loading of token inputs from downstream services and token assembly.
+
 use crate::api::{
-    GeneratorRequest, InventoryFetchRequest, InventoryFetchResponse, LicenseFetchResponse, LicensesListRequest,
-    LicensesListResponse,
+    GeneratorRequest, InventoryFetchRequest, InventoryFetchResponse, InventoryReference, LicenseFetchResponse,
+    LicensesListRequest, LicensesListResponse,
 };
-use crate::model::Claims;
+use crate::countersign::{countersign, ServiceKey};
+use crate::filter::LicenseFilter;
+use crate::kdf::KeyStretching;
+use crate::metrics::Metrics;
+use crate::model::{Claims, InventoryClaim};
+use crate::retry::{is_transient, RetryPolicy};
 use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
+use crate::telemetry::current_trace_context;
+use crate::token::sign_claims;
+use aws_sdk_lambda::operation::invoke::InvokeOutput;
 use aws_sdk_lambda::Client;
 use aws_smithy_types::Blob;
-use hmac::digest::KeyInit;
-use hmac::Hmac;
-use jwt::SignWithKey;
 use serde_json::{from_slice, to_string};
-use sha2::Sha512;
+use std::cmp::min;
+use std::time::Instant;
+use tokio::time::{sleep, timeout};
+use tracing::{info, instrument, warn};
 use uuid::Uuid;
 
 const JWT_INVENTORY_TYPE: &str = "jwt_key";
 
+const INVENTORY_LATENCY_METRIC: &str = "InventoryFetcherLatency";
+const LICENSES_LATENCY_METRIC: &str = "LicensesListerLatency";
+const LICENSE_PAGES_METRIC: &str = "LicensePages";
+const LICENSES_EMBEDDED_METRIC: &str = "LicensesEmbedded";
+
+/// Keys the token is signed with.
+pub struct TokenKeys<'a> {
+    /// Hardware identifier.
+    pub hardware: &'a SecretKey,
+    pub stretching: Option<&'a KeyStretching>,
+    /// Service key countersigning the token.
+    pub service: Option<&'a ServiceKey>,
+}
+
+/// Per-request settings of downstream invocations.
+pub struct InvokeContext<'a> {
+    pub policy: &'a RetryPolicy,
+    /// Point in time after which no more attempts are made.
+    pub deadline: Instant,
+    pub metrics: &'a Metrics,
+}
+
+async fn invoke(
+    client: &Client,
+    lambda: &String,
+    payload: Blob,
+    context: &InvokeContext<'_>,
+    latency_metric: &'static str,
+) -> Result<InvokeOutput, RuntimeError> {
+    let policy = context.policy;
+    let deadline = context.deadline;
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(RuntimeError::DeadlineExceeded);
+        }
+
+        let call = client.invoke().function_name(lambda).payload(payload.clone()).send();
+        let started = Instant::now();
+        let result = timeout(min(policy.invoke_timeout, remaining), call).await;
+        context.metrics.record_duration(latency_metric, started.elapsed());
+
+        match result {
+            Ok(Ok(output)) => return Ok(output),
+            Ok(Err(error)) if attempt >= policy.max_attempts || !is_transient(&error) => return Err(error.into()),
+            Err(_) if attempt >= policy.max_attempts => return Err(RuntimeError::InvokeTimeout),
+            // throttled, transient failure or single call timed out - try again
+            Ok(Err(error)) => warn!(lambda = %lambda, attempt, %error, "transient downstream failure, retrying"),
+            Err(_) => warn!(lambda = %lambda, attempt, "downstream call timed out, retrying"),
+        }
+
+        let delay = policy.backoff(attempt);
+        if Instant::now() + delay >= deadline {
+            return Err(RuntimeError::DeadlineExceeded);
+        }
+
+        sleep(delay).await;
+    }
+}
+
+// client span of the downstream call, its context is propagated within the request
+#[instrument(skip_all, fields(otel.kind = "client", lambda = %lambda))]
+async fn fetch_inventory(
+    client: &Client,
+    lambda: &String,
+    customer_id: &Uuid,
+    vessel_id: &Uuid,
+    inventory: &InventoryReference,
+    context: &InvokeContext<'_>,
+) -> Result<Option<InventoryFetchResponse>, RuntimeError> {
+    let request = Blob::new(to_string(&InventoryFetchRequest {
+        customer_id: *customer_id,
+        vessel_id: *vessel_id,
+        inventory_type: inventory.inventory_type.clone(),
+        inventory_id: inventory.inventory_id.clone(),
+        trace_context: current_trace_context(),
+    })?);
+
+    match invoke(client, lambda, request, context, INVENTORY_LATENCY_METRIC)
+        .await?
+        .payload()
+    {
+        Some(result) => Ok(Some(from_slice(result.as_ref())?)),
+        None => Ok(None),
+    }
+}
+
+// key itself must never be logged
+#[instrument(skip_all, fields(inventory_key = %inventory_key))]
 pub async fn load_key(
     client: &Client,
     lambda: &String,
     customer_id: &Uuid,
     vessel_id: &Uuid,
     inventory_key: String,
-) -> Result<String, RuntimeError> {
-    if let Some(result) = client
-        .invoke()
-        .function_name(lambda)
-        .payload(Blob::new(to_string(&InventoryFetchRequest {
-            customer_id: *customer_id,
-            vessel_id: *vessel_id,
-            inventory_type: JWT_INVENTORY_TYPE.into(),
-            inventory_id: inventory_key,
-        })?))
-        .send()
+    context: &InvokeContext<'_>,
+) -> Result<SecretKey, RuntimeError> {
+    let inventory = InventoryReference {
+        inventory_type: JWT_INVENTORY_TYPE.into(),
+        inventory_id: inventory_key,
+    };
+
+    fetch_inventory(client, lambda, customer_id, vessel_id, &inventory, context)
+        .await?
+        .and_then(|response| response.serial_number)
+        .ok_or(RuntimeError::MissingKey)
+}
+
+#[instrument(skip_all, fields(inventory_type = %inventory.inventory_type, inventory_id = %inventory.inventory_id))]
+pub async fn load_inventory(
+    client: &Client,
+    lambda: &String,
+    customer_id: &Uuid,
+    vessel_id: &Uuid,
+    inventory: &InventoryReference,
+    context: &InvokeContext<'_>,
+) -> Result<InventoryClaim, RuntimeError> {
+    fetch_inventory(client, lambda, customer_id, vessel_id, inventory, context)
+        .await?
+        .map(InventoryClaim::from)
+        .ok_or_else(|| RuntimeError::MissingInventory(inventory.inventory_type.clone(), inventory.inventory_id.clone()))
+}
+
+// client span of the downstream call, its context is propagated within the request
+#[instrument(skip_all, fields(otel.kind = "client", lambda = %lambda))]
+async fn fetch_licenses_page(
+    client: &Client,
+    lambda: &String,
+    customer_id: &Uuid,
+    vessel_id: &Uuid,
+    page_token: Option<String>,
+    context: &InvokeContext<'_>,
+) -> Result<Option<LicensesListResponse>, RuntimeError> {
+    let request = Blob::new(to_string(&LicensesListRequest {
+        customer_id: *customer_id,
+        vessel_id: *vessel_id,
+        page_token,
+        trace_context: current_trace_context(),
+    })?);
+
+    match invoke(client, lambda, request, context, LICENSES_LATENCY_METRIC)
         .await?
         .payload()
     {
-        let response = from_slice::<InventoryFetchResponse>(result.as_ref())?;
-
-        response.serial_number.ok_or(RuntimeError::MissingKey)
-    } else {
-        Err(RuntimeError::MissingKey)
+        Some(result) => Ok(Some(from_slice(result.as_ref())?)),
+        None => Ok(None),
     }
 }
 
+#[instrument(skip_all)]
 pub async fn load_licenses(
     client: &Client,
     lambda: &String,
     customer_id: &Uuid,
     vessel_id: &Uuid,
+    context: &InvokeContext<'_>,
 ) -> Result<Vec<LicenseFetchResponse>, RuntimeError> {
-    let mut request = LicensesListRequest {
-        customer_id: *customer_id,
-        vessel_id: *vessel_id,
-        page_token: None,
-    };
+    let mut page_token = None;
     let mut licenses = vec![];
+    let mut pages = 0;
 
     loop {
-        if let Some(result) = client
-            .invoke()
-            .function_name(lambda)
-            .payload(Blob::new(to_string(&request)?))
-            .send()
-            .await?
-            .payload()
-        {
-            let response = from_slice::<LicensesListResponse>(result.as_ref())?;
-
-            request.page_token = response.page_token;
-
-            licenses.extend(response.licenses);
-        }
+        pages += 1;
 
-        if request.page_token.is_none() {
+        page_token = match fetch_licenses_page(client, lambda, customer_id, vessel_id, page_token, context).await? {
+            Some(response) => {
+                licenses.extend(response.licenses);
+                response.page_token
+            }
+            None => None,
+        };
+
+        if page_token.is_none() {
             break;
         }
     }
 
+    info!(pages, licenses = licenses.len(), "licenses loaded");
+    context.metrics.record_count(LICENSE_PAGES_METRIC, pages);
+
     Ok(licenses)
 }
 
+#[instrument(skip_all)]
 pub fn assemble_token(
     request: GeneratorRequest,
-    key: String,
+    keys: &TokenKeys<'_>,
     licenses: Vec<LicenseFetchResponse>,
+    filter: &LicenseFilter,
+    inventory: Vec<InventoryClaim>,
+    metrics: &Metrics,
 ) -> Result<String, RuntimeError> {
+    // generate list of claims
+    let claims = Claims {
+        minion_id: request.minion_id,
+        inventory,
+        ..Claims::from_input(
+            licenses,
+            filter,
+            &request.customer_id,
+            &request.vessel_id,
+            request.issuer,
+            request.audience,
+            request.license_expiry,
+        )
+    };
+    let claims = match request.claims_version {
+        Some(version) => claims.with_version(version),
+        None => claims,
+    };
+
+    info!(
+        licenses = claims.licenses.len(),
+        inventory = claims.inventory.len(),
+        version = u8::from(claims.version),
+        "claims assembled"
+    );
+    metrics.record_count(LICENSES_EMBEDDED_METRIC, claims.licenses.len());
+
     // key used for generating signature based on known hardware descriptors
-    let key: Hmac<Sha512> = Hmac::new_from_slice(key.as_bytes())?;
+    let token = sign_claims(&claims, keys.hardware, keys.stretching)?;
 
-    // generate list of claims
-    let claims = Claims::from_input(
-        licenses,
-        &request.customer_id,
-        &request.vessel_id,
-        request.issuer,
-        request.audience,
+    match keys.service {
+        Some(service) => countersign(&token, service),
+        None => Ok(token),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::countersign::ServiceKey;
+    use crate::filter::LicenseFilter;
+    use crate::generator::{assemble_token, TokenKeys};
+    use crate::jwks::Jwks;
+    use crate::keystore::KeyRing;
+    use crate::metrics::Metrics;
+    use crate::runtime_error::RuntimeError;
+    use crate::verifier::{StaticHardwareId, Verifier};
+    use chrono::Utc;
+    use ed25519_dalek::SigningKey;
+    use serde_json::{from_str, to_string};
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    const KEY: &str = "qwerta";
+    const ISSUER: &str = "unit-test";
+    const REQUEST: &str = concat!(
+        r#"{"customerId":"00000000-0000-0000-0000-000000000000","vesselId":"00000000-0000-0000-0000-000000000001","#,
+        r#""inventoryKey":"local","issuer":"unit-test","audience":"local"}"#,
     );
 
-    claims.sign_with_key(&key).map_err(RuntimeError::from)
+    fn generate(service: &ServiceKey) -> String {
+        let keys = TokenKeys {
+            hardware: &KEY.into(),
+            stretching: None,
+            service: Some(service),
+        };
+
+        assemble_token(
+            from_str(REQUEST).unwrap(),
+            &keys,
+            vec![],
+            &LicenseFilter::all(),
+            vec![],
+            &Metrics::new("IVMS/Test", "generate"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_generated_token_against_jwks() {
+        let active = ServiceKey::new(SigningKey::from_bytes(&[1; 32]));
+        let retired = ServiceKey::new(SigningKey::from_bytes(&[2; 32]));
+        // JWKS function is deployed without any private keys, so the ring holds just the public parts
+        let ring = KeyRing::new(
+            vec![],
+            vec![active.verifying_key(), retired.verifying_key()],
+            Duration::from_secs(86_400),
+        );
+        let published = ring.public_keys(Utc::now()).iter().collect::<Jwks>();
+
+        // what the verifier gets over the wire
+        let jwks: Jwks = from_str(&to_string(&published).unwrap()).unwrap();
+        let verifier = Verifier::new(StaticHardwareId(Rc::new(KEY.into())))
+            .with_jwks(&jwks)
+            .unwrap();
+
+        assert_eq!(ISSUER, verifier.verify(&generate(&active)).unwrap().issuer);
+        assert_eq!(ISSUER, verifier.verify(&generate(&retired)).unwrap().issuer);
+        assert!(matches!(
+            verifier.verify(&generate(&ServiceKey::new(SigningKey::from_bytes(&[3; 32])))),
+            Err(RuntimeError::UnknownServiceKey(_))
+        ));
+    }
 }
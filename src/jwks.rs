@@ -0,0 +1,118 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! JSON Web Key Set of the service public keys.
+
+use crate::countersign::{key_id, COUNTERSIGNATURE_ALGORITHM};
+use crate::runtime_error::RuntimeError;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+
+const KEY_TYPE: &str = "OKP";
+const CURVE: &str = "Ed25519";
+const SIGNATURE_USE: &str = "sig";
+
+/// Public key in RFC 8037 (`OKP`) form.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    pub kid: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+}
+
+impl From<&VerifyingKey> for Jwk {
+    fn from(key: &VerifyingKey) -> Self {
+        Self {
+            kty: KEY_TYPE.to_string(),
+            crv: CURVE.to_string(),
+            x: URL_SAFE_NO_PAD.encode(key.to_bytes()),
+            kid: key_id(key),
+            alg: Some(COUNTERSIGNATURE_ALGORITHM.to_string()),
+            key_use: Some(SIGNATURE_USE.to_string()),
+        }
+    }
+}
+
+impl Jwk {
+    pub fn verifying_key(&self) -> Result<VerifyingKey, RuntimeError> {
+        if self.kty != KEY_TYPE || self.crv != CURVE {
+            return Err(RuntimeError::UnsupportedJwk(self.kid.clone()));
+        }
+
+        let bytes = URL_SAFE_NO_PAD
+            .decode(&self.x)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| RuntimeError::UnsupportedJwk(self.kid.clone()))?;
+
+        VerifyingKey::from_bytes(&bytes).map_err(|_| RuntimeError::UnsupportedJwk(self.kid.clone()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+impl<'a> FromIterator<&'a VerifyingKey> for Jwks {
+    fn from_iter<I: IntoIterator<Item = &'a VerifyingKey>>(keys: I) -> Self {
+        Self {
+            keys: keys.into_iter().map(Jwk::from).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jwks::{Jwk, Jwks};
+    use crate::runtime_error::RuntimeError;
+    use ed25519_dalek::SigningKey;
+    use serde_json::{json, to_value};
+
+    #[test]
+    fn serialize_rfc8037_key() {
+        // RFC 8037 appendix A.2
+        let key = SigningKey::from_bytes(&[
+            0x9d, 0x61, 0xb1, 0x9d, 0xef, 0xfd, 0x5a, 0x60, 0xba, 0x84, 0x4a, 0xf4, 0x92, 0xec, 0x2c, 0xc4, 0x44, 0x49,
+            0xc5, 0x69, 0x7b, 0x32, 0x69, 0x19, 0x70, 0x3b, 0xac, 0x03, 0x1c, 0xae, 0x7f, 0x60,
+        ]);
+
+        assert_eq!(
+            json!({
+                "keys": [{
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+                    "kid": "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k",
+                    "alg": "EdDSA",
+                    "use": "sig",
+                }],
+            }),
+            to_value([key.verifying_key()].iter().collect::<Jwks>()).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_unsupported_key() {
+        let jwk = Jwk {
+            kty: "EC".to_string(),
+            crv: "P-256".to_string(),
+            x: "f83OJ3D2xF1Bg8vub9tLe1gHMzV76e8Tus9uPHvRVEU".to_string(),
+            kid: "ec".to_string(),
+            alg: None,
+            key_use: None,
+        };
+
+        assert!(matches!(jwk.verifying_key(), Err(RuntimeError::UnsupportedJwk(kid)) if kid == "ec"));
+    }
+}
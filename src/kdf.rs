@@ -0,0 +1,271 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Stretching of low-entropy hardware identifiers into signing keys.
+
+use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
+use argon2::{Algorithm, Argon2, Params, Version};
+use pbkdf2::pbkdf2_hmac;
+use serde::{Deserialize, Serialize};
+use sha2::Sha512;
+use std::rc::Rc;
+use zeroize::Zeroizing;
+
+// matches SHA-512 block used by the HMAC
+const DERIVED_KEY_LENGTH: usize = 64;
+
+/// Key derivation function with its parameters - recorded in the token header, verifiers compare it with their own.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(tag = "alg")]
+pub enum Kdf {
+    #[serde(rename = "argon2id")]
+    Argon2id {
+        /// Memory cost in KiB.
+        #[serde(rename = "m", default = "default_argon2_memory")]
+        memory: u32,
+        #[serde(rename = "t", default = "default_argon2_iterations")]
+        iterations: u32,
+        #[serde(rename = "p", default = "default_argon2_parallelism")]
+        parallelism: u32,
+    },
+    #[serde(rename = "pbkdf2-sha512")]
+    Pbkdf2 {
+        #[serde(rename = "i", default = "default_pbkdf2_iterations")]
+        iterations: u32,
+    },
+}
+
+// OWASP recommended minimums
+
+fn default_argon2_memory() -> u32 {
+    19 * 1024
+}
+
+fn default_argon2_iterations() -> u32 {
+    2
+}
+
+fn default_argon2_parallelism() -> u32 {
+    1
+}
+
+fn default_pbkdf2_iterations() -> u32 {
+    210_000
+}
+
+/// Range of the derivation parameters, both ends inclusive - parameters of any other function are out of the range.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct KdfBounds {
+    pub min: Kdf,
+    pub max: Kdf,
+}
+
+/// Key stretching settings of the service.
+#[derive(Clone)]
+pub struct KeyStretching {
+    /// Derivation new tokens are signed with.
+    pub kdf: Kdf,
+    /// Derivation parameters accepted in token headers, so that rotating `kdf` within them keeps issued tokens valid.
+    pub bounds: KdfBounds,
+    /// Secret shared with the verifiers, never recorded in tokens.
+    pub pepper: Option<Rc<SecretKey>>,
+}
+
+impl Kdf {
+    /// Checks whether the parameters can be used for derivation.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => Params::new(*memory, *iterations, *parallelism, Some(DERIVED_KEY_LENGTH))
+                .map(drop)
+                .map_err(|error| error.to_string()),
+            Self::Pbkdf2 { iterations: 0 } => Err("iterations must be at least 1".to_string()),
+            Self::Pbkdf2 { .. } => Ok(()),
+        }
+    }
+
+    /// Derives signing key from the hardware identifier. Salt binds the key to the token subject, optional pepper is
+    /// a secret shared by the service and the verifiers - used as Argon2 secret or appended to PBKDF2 salt.
+    pub fn derive(
+        &self,
+        key: &SecretKey,
+        salt: &[u8],
+        pepper: Option<&SecretKey>,
+    ) -> Result<Zeroizing<Vec<u8>>, RuntimeError> {
+        let pepper = pepper.map(|pepper| pepper.expose().as_bytes()).unwrap_or_default();
+        let mut derived = Zeroizing::new(vec![0; DERIVED_KEY_LENGTH]);
+
+        match self {
+            Self::Argon2id {
+                memory,
+                iterations,
+                parallelism,
+            } => Argon2::new_with_secret(
+                pepper,
+                Algorithm::Argon2id,
+                Version::V0x13,
+                Params::new(*memory, *iterations, *parallelism, Some(DERIVED_KEY_LENGTH))?,
+            )?
+            .hash_password_into(key.expose().as_bytes(), salt, &mut derived)?,
+            Self::Pbkdf2 { iterations } => pbkdf2_hmac::<Sha512>(
+                key.expose().as_bytes(),
+                &[salt, pepper].concat(),
+                *iterations,
+                &mut derived,
+            ),
+        }
+
+        Ok(derived)
+    }
+}
+
+impl KdfBounds {
+    pub fn contains(&self, kdf: &Kdf) -> bool {
+        match (self.min, *kdf, self.max) {
+            (
+                Kdf::Argon2id {
+                    memory: min_memory,
+                    iterations: min_iterations,
+                    parallelism: min_parallelism,
+                },
+                Kdf::Argon2id {
+                    memory,
+                    iterations,
+                    parallelism,
+                },
+                Kdf::Argon2id {
+                    memory: max_memory,
+                    iterations: max_iterations,
+                    parallelism: max_parallelism,
+                },
+            ) => {
+                (min_memory..=max_memory).contains(&memory)
+                    && (min_iterations..=max_iterations).contains(&iterations)
+                    && (min_parallelism..=max_parallelism).contains(&parallelism)
+            }
+            (
+                Kdf::Pbkdf2 {
+                    iterations: min_iterations,
+                },
+                Kdf::Pbkdf2 { iterations },
+                Kdf::Pbkdf2 {
+                    iterations: max_iterations,
+                },
+            ) => (min_iterations..=max_iterations).contains(&iterations),
+            _ => false,
+        }
+    }
+}
+
+impl From<Kdf> for KdfBounds {
+    fn from(kdf: Kdf) -> Self {
+        Self { min: kdf, max: kdf }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kdf::{Kdf, KdfBounds};
+    use serde_json::{from_str, to_string};
+
+    const KEY: &str = "qwerta";
+    const SALT: &[u8] = b"00000000-0000-0000-0000-000000000000:00000000-0000-0000-0000-000000000001";
+
+    // cheap parameters, derivation cost doesn't matter here
+    const ARGON2: Kdf = Kdf::Argon2id {
+        memory: 64,
+        iterations: 1,
+        parallelism: 1,
+    };
+    const PBKDF2: Kdf = Kdf::Pbkdf2 { iterations: 10 };
+
+    #[test]
+    fn derive_reproducible_keys() {
+        for kdf in [ARGON2, PBKDF2] {
+            let derived = kdf.derive(&KEY.into(), SALT, None).unwrap();
+
+            assert_eq!(64, derived.len());
+            assert_eq!(derived, kdf.derive(&KEY.into(), SALT, None).unwrap());
+            assert_ne!(derived, kdf.derive(&KEY.into(), b"other-subject", None).unwrap());
+            assert_ne!(derived, kdf.derive(&KEY.into(), SALT, Some(&"pepper".into())).unwrap());
+        }
+    }
+
+    #[test]
+    fn serialize_parameters() {
+        assert_eq!(
+            "{\"alg\":\"argon2id\",\"m\":64,\"t\":1,\"p\":1}",
+            to_string(&ARGON2).unwrap()
+        );
+        assert_eq!("{\"alg\":\"pbkdf2-sha512\",\"i\":10}", to_string(&PBKDF2).unwrap());
+    }
+
+    #[test]
+    fn deserialize_default_parameters() {
+        assert_eq!(
+            Kdf::Argon2id {
+                memory: 19456,
+                iterations: 2,
+                parallelism: 1,
+            },
+            from_str("{\"alg\":\"argon2id\"}").unwrap()
+        );
+        assert_eq!(
+            Kdf::Pbkdf2 { iterations: 210_000 },
+            from_str("{\"alg\":\"pbkdf2-sha512\"}").unwrap()
+        );
+    }
+
+    #[test]
+    fn validate_parameters() {
+        assert!(ARGON2.validate().is_ok());
+        assert!(PBKDF2.validate().is_ok());
+        assert!(Kdf::Pbkdf2 { iterations: 0 }.validate().is_err());
+        assert!(Kdf::Argon2id {
+            memory: 1,
+            iterations: 1,
+            parallelism: 1,
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn check_bounds() {
+        let bounds = KdfBounds {
+            min: ARGON2,
+            max: Kdf::Argon2id {
+                memory: 128,
+                iterations: 2,
+                parallelism: 1,
+            },
+        };
+
+        assert!(bounds.contains(&ARGON2));
+        assert!(bounds.contains(&Kdf::Argon2id {
+            memory: 96,
+            iterations: 2,
+            parallelism: 1,
+        }));
+        assert!(!bounds.contains(&Kdf::Argon2id {
+            memory: 256,
+            iterations: 1,
+            parallelism: 1,
+        }));
+        assert!(!bounds.contains(&Kdf::Argon2id {
+            memory: 64,
+            iterations: 1,
+            parallelism: 2,
+        }));
+        assert!(!bounds.contains(&PBKDF2));
+        assert!(KdfBounds::from(PBKDF2).contains(&PBKDF2));
+        assert!(!KdfBounds::from(PBKDF2).contains(&Kdf::Pbkdf2 { iterations: 11 }));
+    }
+}
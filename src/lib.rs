@@ -0,0 +1,58 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! License file generation for IVMS Salt states.
+//!
+//! Licenses assigned to a vessel are embedded as claims of a JWT that is signed with a hardware identifier of the
+//! target machine, so that it can only be verified by that particular hardware instance. By default crate exposes
+//! just the building blocks that can be reused by the vessel-side tooling:
+//!
+//! -   [`model`] - claims model of the token;
+//! -   [`entitlements`] - queries over licenses embedded in the claims;
+//! -   [`token`] - signing and decoding tokens;
+//! -   [`verifier`] - verification of tokens against hardware identifier of the machine;
+//! -   [`jwks`] and [`countersign`] - publishing and checking service counter-signatures.
+//!
+//! The Lambda service itself (`handlers` and everything it pulls in) is built only with the `lambda` feature, which is
+//! enabled by default - vessel-side consumers should depend on the crate with `default-features = false`.
+
+#[cfg(feature = "lambda")]
+pub mod api;
+#[cfg(feature = "lambda")]
+pub mod cache;
+#[cfg(feature = "lambda")]
+pub mod config;
+pub mod countersign;
+#[cfg(feature = "lambda")]
+pub mod diff;
+pub mod entitlements;
+#[cfg(feature = "lambda")]
+pub mod filter;
+#[cfg(feature = "lambda")]
+pub mod generator;
+#[cfg(feature = "lambda")]
+pub mod handlers;
+pub mod jwks;
+pub mod kdf;
+#[cfg(feature = "lambda")]
+pub mod keystore;
+#[cfg(feature = "lambda")]
+pub mod logging;
+#[cfg(feature = "lambda")]
+pub mod metrics;
+pub mod model;
+#[cfg(feature = "lambda")]
+pub mod retry;
+#[cfg(feature = "lambda")]
+pub mod revocation;
+pub mod runtime_error;
+pub mod secret;
+#[cfg(feature = "lambda")]
+pub mod strength;
+#[cfg(feature = "lambda")]
+pub mod telemetry;
+pub mod token;
+pub mod verifier;
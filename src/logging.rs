@@ -0,0 +1,107 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Structured (JSON lines) logging of the service.
+
+use opentelemetry_sdk::trace::Tracer;
+use std::io::stdout;
+use std::sync::OnceLock;
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::{set_global_default, SetGlobalDefaultError};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+// global subscriber can be set just once per process
+static INSTALLED: OnceLock<()> = OnceLock::new();
+
+/// Builds subscriber writing each event as a single JSON line, together with fields of all the spans it was emitted
+/// within - so every line can be tied to the Lambda request and the vessel it concerns.
+pub fn subscriber<W>(writer: W) -> impl Subscriber + for<'span> LookupSpan<'span> + Send + Sync
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .json()
+        .flatten_event(true)
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_writer(writer)
+        .finish()
+}
+
+/// Installs JSON logging to the standard output, where Lambda forwards it to CloudWatch. When tracer is given, spans
+/// are additionally exported through it.
+///
+/// Verbosity is controlled with `RUST_LOG` directives, `info` level is used when not set. Subsequent calls keep the
+/// already installed logging - but it still fails if any other subscriber was installed globally before.
+pub fn init_logging(tracer: Option<Tracer>) -> Result<(), SetGlobalDefaultError> {
+    if INSTALLED.get().is_some() {
+        return Ok(());
+    }
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .from_env_lossy();
+
+    set_global_default(
+        subscriber(stdout)
+            .with(filter)
+            .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer))),
+    )?;
+    let _ = INSTALLED.set(());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logging::subscriber;
+    use serde_json::{from_str, Value};
+    use std::io::{Result, Write};
+    use std::sync::{Arc, Mutex};
+    use tracing::subscriber::with_default;
+    use tracing::{info, info_span};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().write(data)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn log_json_lines_with_span_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+
+        with_default(subscriber(move || writer.clone()), || {
+            let _request = info_span!("generate_license_file", request_id = "abc", vessel_id = "foo").entered();
+            let _step = info_span!("load_licenses").entered();
+
+            info!(pages = 2, "licenses loaded");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = from_str(output.lines().next().unwrap()).unwrap();
+
+        assert_eq!("licenses loaded", line["message"]);
+        assert_eq!(2, line["pages"]);
+        assert_eq!("INFO", line["level"]);
+        assert_eq!("generate_license_file", line["spans"][0]["name"]);
+        assert_eq!("abc", line["spans"][0]["request_id"]);
+        assert_eq!("foo", line["spans"][0]["vessel_id"]);
+        assert_eq!("load_licenses", line["spans"][1]["name"]);
+    }
+}
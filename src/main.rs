@@ -4,70 +4,55 @@
  * @copyright 2023 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
-#![feature(fn_traits)]
-#![feature(future_join)]
-#![feature(unboxed_closures)]
+// instrumented handler futures nest deeper than the default limit allows
+#![recursion_limit = "256"]
 
-mod api;
-mod generator;
-mod model;
-mod runtime_error;
-
-use crate::api::{ApiError, GeneratorRequest, GeneratorResponse};
-use crate::generator::{assemble_token, load_key, load_licenses};
-use crate::runtime_error::RuntimeError;
 use aws_config::load_defaults;
-use aws_sdk_lambda::Client as LambdaClient;
 use aws_smithy_runtime_api::client::behavior_version::BehaviorVersion;
-use lambda_runtime::{Error, LambdaEvent};
-use std::env::var;
-use std::future::{join, Future};
-use std::rc::Rc;
+use ivms_salt_extractor::config::Config;
+use ivms_salt_extractor::handlers::{serve, startup};
+use ivms_salt_extractor::runtime_error::RuntimeError;
+use lambda_runtime::Error;
+use std::env::{args, var};
+use std::process::exit;
 use tokio::main as tokio_main;
-use wrzasqpl_commons_aws::{run_lambda, LambdaError};
 
-fn generate_license_file(
-    lambda: Rc<LambdaClient>,
-    inventory_fetcher: Rc<String>,
-    licenses_lister: Rc<String>,
-) -> impl Fn<(LambdaEvent<GeneratorRequest>,), Output = impl Future<Output = Result<GeneratorResponse, ApiError>>> {
-    move |event: LambdaEvent<GeneratorRequest>| {
-        let lambda = lambda.clone();
-        let inventory_fetcher = inventory_fetcher.clone();
-        let licenses_lister = licenses_lister.clone();
+const CHECK_CONFIG_FLAG: &str = "--check-config";
+// handlers invoking downstream Lambdas
+const DOWNSTREAM_HANDLERS: [&str; 3] = ["extractor:generate", "extractor:diff", "extractor:renew"];
 
-        async move {
-            let customer_id = event.payload.customer_id;
-            let vessel_id = event.payload.vessel_id;
+#[tokio_main]
+async fn main() -> Result<(), Error> {
+    let handler = var("_HANDLER").ok();
+    // without the handler (eg. when checking configuration in a pipeline) the full setup is expected
+    let config = match handler.as_deref() {
+        Some(handler) if !DOWNSTREAM_HANDLERS.contains(&handler) => Config::from_env(),
+        _ => Config::from_env().and_then(Config::require_downstream),
+    };
+
+    // validates configuration without starting the runtime - handy for deployment pipelines
+    if args().any(|arg| arg == CHECK_CONFIG_FLAG) {
+        // key stores are only read when the ring is built, so it's built here as well
+        match config.and_then(|config| config.key_ring()) {
+            Ok(_) => println!("Configuration is valid."),
+            Err(RuntimeError::InvalidConfig(problems)) => {
+                for problem in problems {
+                    eprintln!("{problem}");
+                }
+                exit(1);
+            }
+            Err(error) => return Err(error.into()),
+        }
 
-            let (inventory, licenses) = join!(
-                load_key(
-                    lambda.as_ref(),
-                    inventory_fetcher.as_ref(),
-                    &customer_id,
-                    &vessel_id,
-                    event.payload.inventory_key.clone()
-                ),
-                load_licenses(lambda.as_ref(), licenses_lister.as_ref(), &customer_id, &vessel_id)
-            )
-            .await;
+        return Ok(());
+    }
 
-            let token = assemble_token(event.payload, inventory?, licenses?)?;
+    let config = config?;
 
-            Ok(GeneratorResponse::new(token))
-        }
-    }
-}
+    let sdk_config = &load_defaults(BehaviorVersion::latest()).await;
 
-#[tokio_main]
-async fn main() -> Result<(), Error> {
-    let config = &load_defaults(BehaviorVersion::v2023_11_09()).await;
+    let extractor = startup(sdk_config, config)?;
 
-    run_lambda!(
-        "extractor:generate": generate_license_file(
-            Rc::new(LambdaClient::new(config)),
-            Rc::new(var("INVENTORY_FETCHER").map_err(RuntimeError::ClientConfigLoadingError)?),
-            Rc::new(var("LICENSES_LISTER").map_err(RuntimeError::ClientConfigLoadingError)?),
-        ),
-    )
+    // runtime loop only ends on failure - spans are flushed after each invocation instead of on shutdown
+    serve(&extractor, handler.as_deref().unwrap_or_default()).await
 }
@@ -0,0 +1,151 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Metrics in CloudWatch Embedded Metric Format.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+pub const DURATION_METRIC: &str = "Duration";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub enum Unit {
+    Milliseconds,
+    Count,
+}
+
+/// Metrics of a single handler invocation, published as one EMF document once the outcome is known.
+pub struct Metrics {
+    namespace: String,
+    operation: &'static str,
+    started: Instant,
+    values: RefCell<BTreeMap<&'static str, (Unit, Vec<f64>)>>,
+}
+
+impl Metrics {
+    pub fn new(namespace: impl Into<String>, operation: &'static str) -> Self {
+        Self {
+            namespace: namespace.into(),
+            operation,
+            started: Instant::now(),
+            values: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Metric recorded multiple times (eg. latency of each downstream call) is published with all its values.
+    pub fn record(&self, name: &'static str, unit: Unit, value: f64) {
+        self.values
+            .borrow_mut()
+            .entry(name)
+            .or_insert_with(|| (unit, vec![]))
+            .1
+            .push(value);
+    }
+
+    pub fn record_duration(&self, name: &'static str, duration: Duration) {
+        self.record(name, Unit::Milliseconds, duration.as_secs_f64() * 1000.0);
+    }
+
+    pub fn record_count(&self, name: &'static str, count: usize) {
+        self.record(name, Unit::Count, count as f64);
+    }
+
+    /// Builds EMF document of recorded metrics, dimensioned by operation and outcome.
+    pub fn document(&self, outcome: &str, timestamp: DateTime<Utc>) -> Value {
+        let values = self.values.borrow();
+        let mut document = Map::new();
+
+        document.insert(
+            "_aws".to_string(),
+            json!({
+                "Timestamp": timestamp.timestamp_millis(),
+                "CloudWatchMetrics": [{
+                    "Namespace": self.namespace,
+                    "Dimensions": [["Operation"], ["Operation", "Outcome"]],
+                    "Metrics": values
+                        .iter()
+                        .map(|(name, (unit, _))| json!({"Name": name, "Unit": unit}))
+                        .collect::<Vec<_>>(),
+                }],
+            }),
+        );
+        document.insert("Operation".to_string(), json!(self.operation));
+        document.insert("Outcome".to_string(), json!(outcome));
+
+        for (name, (_, samples)) in values.iter() {
+            document.insert(
+                name.to_string(),
+                match samples.as_slice() {
+                    [single] => json!(single),
+                    _ => json!(samples),
+                },
+            );
+        }
+
+        Value::Object(document)
+    }
+
+    /// Records total duration and writes the document to the standard output, where CloudWatch picks it up.
+    pub fn publish(&self, outcome: &str) {
+        self.record_duration(DURATION_METRIC, self.started.elapsed());
+
+        println!("{}", self.document(outcome, Utc::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{Metrics, Unit};
+    use chrono::{TimeZone, Utc};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn build_emf_document() {
+        let metrics = Metrics::new("IVMS/Test", "generate");
+        metrics.record_duration("LicensesListerLatency", Duration::from_millis(20));
+        metrics.record_duration("LicensesListerLatency", Duration::from_millis(30));
+        metrics.record_count("LicensePages", 2);
+
+        let document = metrics.document("MissingKey", Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0).unwrap());
+
+        assert_eq!(
+            json!({
+                "_aws": {
+                    "Timestamp": 1296395880000_i64,
+                    "CloudWatchMetrics": [{
+                        "Namespace": "IVMS/Test",
+                        "Dimensions": [["Operation"], ["Operation", "Outcome"]],
+                        "Metrics": [
+                            {"Name": "LicensePages", "Unit": "Count"},
+                            {"Name": "LicensesListerLatency", "Unit": "Milliseconds"},
+                        ],
+                    }],
+                },
+                "Operation": "generate",
+                "Outcome": "MissingKey",
+                "LicensePages": 2.0,
+                "LicensesListerLatency": [20.0, 30.0],
+            }),
+            document
+        );
+    }
+
+    #[test]
+    fn record_samples() {
+        let metrics = Metrics::new("IVMS/Test", "diff");
+        metrics.record("Custom", Unit::Count, 1.0);
+
+        let document = metrics.document("Success", Utc::now());
+
+        assert_eq!(1.0, document["Custom"]);
+        assert_eq!("diff", document["Operation"]);
+    }
+}
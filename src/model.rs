@@ -4,54 +4,286 @@
  * @copyright 2023 - 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
-use crate::api::LicenseFetchResponse;
-use chrono::{DateTime, Duration, FixedOffset, Utc};
-use serde::Serialize;
-use std::collections::HashMap;
+//! Claims model of the license token.
+
+#[cfg(feature = "lambda")]
+use crate::api::{InventoryFetchResponse, LicenseFetchResponse};
+#[cfg(feature = "lambda")]
+use crate::filter::LicenseFilter;
+use crate::runtime_error::RuntimeError;
+#[cfg(feature = "lambda")]
+use chrono::Duration;
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
 use uuid::Uuid;
 
+#[cfg(feature = "lambda")]
 const MICROS_PER_TWO_YEARS: i64 = 62_208_000_000_000;
 
-#[derive(Serialize)]
+/// Claims layout version, emitted as `ivms:ver` claim - except for V1, so that such tokens stay the same as before the
+/// layout was versioned.
+///
+/// Verifiers reject tokens with versions they don't know, instead of silently misreading them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum ClaimsVersion {
+    /// License expiration dates as RFC 3339 dates; tokens without `ivms:ver` claim are also this version.
+    #[default]
+    V1,
+    /// License expiration dates as Unix timestamps.
+    V2,
+}
+
+impl TryFrom<u8> for ClaimsVersion {
+    type Error = String;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            _ => Err(format!("unsupported claims version {version}")),
+        }
+    }
+}
+
+impl ClaimsVersion {
+    fn is_v1(&self) -> bool {
+        *self == Self::V1
+    }
+}
+
+impl From<ClaimsVersion> for u8 {
+    fn from(version: ClaimsVersion) -> Self {
+        match version {
+            ClaimsVersion::V1 => 1,
+            ClaimsVersion::V2 => 2,
+        }
+    }
+}
+
+/// Intended recipients of the token - single one is emitted as a string, multiple ones as an array.
+///
+/// Empty list is rejected, as the token would be accepted by no verifier checking the audience, and single-element list
+/// is read (and emitted) as single audience.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(try_from = "RawAudience", into = "RawAudience")]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+// wire format of the `aud` claim
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum RawAudience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl TryFrom<RawAudience> for Audience {
+    type Error = String;
+
+    fn try_from(audience: RawAudience) -> Result<Self, Self::Error> {
+        match audience {
+            RawAudience::Single(single) => Ok(Self::Single(single)),
+            RawAudience::Multiple(multiple) if multiple.is_empty() => {
+                Err("audience must list at least one recipient".to_string())
+            }
+            RawAudience::Multiple(mut multiple) if multiple.len() == 1 => Ok(Self::Single(multiple.remove(0))),
+            RawAudience::Multiple(multiple) => Ok(Self::Multiple(multiple)),
+        }
+    }
+}
+
+impl From<Audience> for RawAudience {
+    fn from(audience: Audience) -> Self {
+        match audience {
+            Audience::Single(single) => Self::Single(single),
+            Audience::Multiple(mut multiple) if multiple.len() == 1 => Self::Single(multiple.remove(0)),
+            Audience::Multiple(multiple) => Self::Multiple(multiple),
+        }
+    }
+}
+
+impl Audience {
+    pub fn contains(&self, audience: &str) -> bool {
+        match self {
+            Self::Single(single) => single == audience,
+            Self::Multiple(multiple) => multiple.iter().any(|entry| entry == audience),
+        }
+    }
+}
+
+impl From<&str> for Audience {
+    fn from(audience: &str) -> Self {
+        Self::Single(audience.to_string())
+    }
+}
+
+impl From<String> for Audience {
+    fn from(audience: String) -> Self {
+        Self::Single(audience)
+    }
+}
+
+/// How license expiration dates are emitted in the claims.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ExpiryFormat {
+    /// RFC 3339 date with the offset returned by licenses service.
+    #[default]
+    Original,
+    /// RFC 3339 date normalised to UTC.
+    Utc,
+    /// Unix timestamp - only supported by [`ClaimsVersion::V2`] layout.
+    Timestamp,
+}
+
+/// License expiration date - either RFC 3339 date or Unix timestamp, depending on claims layout.
+///
+/// Dates are compared as instants, regardless of representation.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum LicenseExpiry {
+    Timestamp(i64),
+    DateTime(DateTime<FixedOffset>),
+}
+
+impl LicenseExpiry {
+    pub fn to_utc(self) -> DateTime<Utc> {
+        match self {
+            // out of range timestamp can't be trusted, so it's treated as long expired
+            Self::Timestamp(timestamp) => Utc
+                .timestamp_opt(timestamp, 0)
+                .single()
+                .unwrap_or(DateTime::<Utc>::MIN_UTC),
+            Self::DateTime(date) => date.with_timezone(&Utc),
+        }
+    }
+
+    pub fn formatted(date: DateTime<FixedOffset>, format: ExpiryFormat) -> Self {
+        match format {
+            ExpiryFormat::Original => Self::DateTime(date),
+            ExpiryFormat::Utc => Self::DateTime(date.with_timezone(&Utc).fixed_offset()),
+            ExpiryFormat::Timestamp => Self::Timestamp(date.timestamp()),
+        }
+    }
+}
+
+impl From<DateTime<FixedOffset>> for LicenseExpiry {
+    fn from(date: DateTime<FixedOffset>) -> Self {
+        Self::DateTime(date)
+    }
+}
+
+impl PartialEq for LicenseExpiry {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_utc() == other.to_utc()
+    }
+}
+
+impl Eq for LicenseExpiry {}
+
+/// Single license entry of `ivms:licenses` claim.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LicenseClaim {
+    /// Number of seats, `None` means unlimited.
     pub count: Option<u8>,
-    pub expires_at: Option<DateTime<FixedOffset>>,
+    /// License expiration date, `None` means perpetual license.
+    pub expires_at: Option<LicenseExpiry>,
+    /// Allowed license parameters passed through from licenses service.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<String, Value>,
+}
+
+/// Single entry of `ivms:inventory` claim - only non-secret attributes of the inventory entry.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InventoryClaim {
+    pub inventory_type: String,
+    pub inventory_id: String,
+    pub created_at: DateTime<FixedOffset>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aws_instance_id: Option<String>,
+}
+
+#[cfg(feature = "lambda")]
+impl From<InventoryFetchResponse> for InventoryClaim {
+    fn from(inventory: InventoryFetchResponse) -> Self {
+        // serial number is deliberately left out - it's the signing key for `jwt_key` entries
+        Self {
+            inventory_type: inventory.inventory_type,
+            inventory_id: inventory.inventory_id,
+            created_at: inventory.created_at,
+            aws_instance_id: inventory.aws_instance_id,
+        }
+    }
 }
 
-#[derive(Serialize)]
+/// Claims of the license token.
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Claims {
     #[serde(rename = "iss")]
     pub issuer: String,
+    /// `customer:vessel` identifier.
     #[serde(rename = "sub")]
     pub user: String,
+    /// Salt minion the token is bound to.
+    #[serde(rename = "ivms:minion", default, skip_serializing_if = "Option::is_none")]
+    pub minion_id: Option<String>,
     #[serde(rename = "aud")]
-    pub audience: String,
+    pub audience: Audience,
     #[serde(rename = "exp")]
     pub expires_at: i64,
     #[serde(rename = "iat")]
     pub issued_at: i64,
+    /// Licenses by their keys.
     #[serde(rename = "ivms:licenses")]
     pub licenses: HashMap<String, LicenseClaim>,
+    /// Verified hardware facts, in order of the request.
+    #[serde(rename = "ivms:inventory", default, skip_serializing_if = "Vec::is_empty")]
+    pub inventory: Vec<InventoryClaim>,
+    #[serde(rename = "ivms:ver", default, skip_serializing_if = "ClaimsVersion::is_v1")]
+    pub version: ClaimsVersion,
 }
 
+#[cfg(feature = "lambda")]
 impl Claims {
+    /// Builds claims for licenses of given vessel, valid for two years since now.
+    ///
+    /// Only licenses matching the filter are included, so each node learns only about licenses relevant to it, and
+    /// only attributes allowed by the filter are passed through.
     pub fn from_input(
         licenses: Vec<LicenseFetchResponse>,
+        filter: &LicenseFilter,
         customer_id: &Uuid,
         vessel_id: &Uuid,
         issuer: String,
-        audience: String,
+        audience: Audience,
+        expiry_format: ExpiryFormat,
     ) -> Self {
         let mut claims = HashMap::with_capacity(licenses.len());
 
-        for license in licenses {
+        for license in licenses
+            .into_iter()
+            .filter(|license| filter.matches(&license.license_key))
+        {
             claims.insert(
                 license.license_key,
                 LicenseClaim {
                     count: license.count,
-                    expires_at: license.expires_at,
+                    expires_at: license
+                        .expires_at
+                        .map(|expires_at| LicenseExpiry::formatted(expires_at, expiry_format)),
+                    attributes: license
+                        .attributes
+                        .into_iter()
+                        .filter(|(name, _)| filter.allows_attribute(name))
+                        .collect(),
                 },
             );
         }
@@ -59,20 +291,93 @@ impl Claims {
         Self {
             issuer,
             user: format!("{customer_id}:{vessel_id}"),
+            minion_id: None,
             audience,
             // two years
             expires_at: (Utc::now() + Duration::microseconds(MICROS_PER_TWO_YEARS)).timestamp(),
             issued_at: Utc::now().timestamp(),
             licenses: claims,
+            inventory: vec![],
+            // older verifiers expect RFC 3339 dates
+            version: if expiry_format == ExpiryFormat::Timestamp {
+                ClaimsVersion::V2
+            } else {
+                ClaimsVersion::V1
+            },
         }
     }
 }
 
+impl Claims {
+    /// Customer and vessel ids the token was issued for.
+    pub fn subject(&self) -> Result<(Uuid, Uuid), RuntimeError> {
+        let (customer_id, vessel_id) = self.user.split_once(':').ok_or(RuntimeError::MalformedToken)?;
+
+        Ok((customer_id.parse()?, vessel_id.parse()?))
+    }
+
+    /// Format license expiration dates were emitted in - dates all in UTC are taken as normalised ones. Without any
+    /// expiring license there is nothing to tell it from, so default format of the claims layout is assumed.
+    pub fn expiry_format(&self) -> ExpiryFormat {
+        let mut dates = self
+            .licenses
+            .values()
+            .filter_map(|license| license.expires_at)
+            .peekable();
+
+        match dates.peek() {
+            None => match self.version {
+                ClaimsVersion::V1 => ExpiryFormat::Original,
+                ClaimsVersion::V2 => ExpiryFormat::Timestamp,
+            },
+            Some(LicenseExpiry::Timestamp(_)) => ExpiryFormat::Timestamp,
+            Some(LicenseExpiry::DateTime(_)) => {
+                if dates.all(|expires_at| {
+                    matches!(expires_at, LicenseExpiry::DateTime(date) if date.offset().local_minus_utc() == 0)
+                }) {
+                    ExpiryFormat::Utc
+                } else {
+                    ExpiryFormat::Original
+                }
+            }
+        }
+    }
+
+    /// Converts claims to the layout of given version.
+    ///
+    /// Timestamps converted to V1 layout become UTC dates, as the original offset is not known anymore.
+    pub fn with_version(mut self, version: ClaimsVersion) -> Self {
+        for license in self.licenses.values_mut() {
+            license.expires_at = license.expires_at.map(|expires_at| match version {
+                ClaimsVersion::V1 => match expires_at {
+                    LicenseExpiry::Timestamp(_) => LicenseExpiry::DateTime(expires_at.to_utc().fixed_offset()),
+                    date => date,
+                },
+                ClaimsVersion::V2 => LicenseExpiry::Timestamp(expires_at.to_utc().timestamp()),
+            });
+        }
+        self.version = version;
+
+        self
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::api::LicenseFetchResponse;
-    use crate::model::Claims;
-    use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+    #[cfg(feature = "lambda")]
+    use crate::api::{InventoryFetchResponse, LicenseFetchResponse};
+    #[cfg(feature = "lambda")]
+    use crate::filter::LicenseFilter;
+    use crate::model::{Audience, Claims, ClaimsVersion, LicenseClaim, LicenseExpiry};
+    #[cfg(feature = "lambda")]
+    use crate::model::{ExpiryFormat, InventoryClaim};
+    #[cfg(feature = "lambda")]
+    use chrono::DateTime;
+    use chrono::{FixedOffset, TimeZone, Utc};
+    #[cfg(feature = "lambda")]
+    use serde_json::json;
+    use serde_json::{from_str, to_string};
+    use std::collections::{BTreeMap, HashMap};
     use uuid::{uuid, Uuid};
 
     const CUSTOMER_ID: Uuid = uuid!("00000000-0000-0000-0000-000000000000");
@@ -81,10 +386,13 @@ mod tests {
     const AUDIENCE: &str = "test";
     const LICENSE_KEY_0: &str = "foo";
     const COUNT_0: u8 = 12;
+    #[cfg(feature = "lambda")]
     const LICENSE_KEY_1: &str = "bar";
+    #[cfg(feature = "lambda")]
     const LICENSE_KEY_2: &str = "baz";
 
     #[test]
+    #[cfg(feature = "lambda")]
     fn build_claims_from_licenses() {
         let expires_at_1: DateTime<FixedOffset> = Utc
             .with_ymd_and_hms(2011, 1, 30, 13, 58, 0)
@@ -97,31 +405,36 @@ mod tests {
             license_key: LICENSE_KEY_0.to_string(),
             count: Some(COUNT_0),
             expires_at: None,
+            attributes: BTreeMap::new(),
         };
         let license1 = LicenseFetchResponse {
             license_key: LICENSE_KEY_1.to_string(),
             count: None,
             expires_at: Some(expires_at_1),
+            attributes: BTreeMap::new(),
         };
         let license2 = LicenseFetchResponse {
             license_key: LICENSE_KEY_2.to_string(),
             count: None,
             expires_at: None,
+            attributes: BTreeMap::new(),
         };
 
         let claims = Claims::from_input(
             vec![license0, license1, license2],
+            &LicenseFilter::all(),
             &CUSTOMER_ID,
             &VESSEL_ID,
             ISSUER.to_string(),
-            AUDIENCE.to_string(),
+            AUDIENCE.into(),
+            ExpiryFormat::Original,
         );
 
         let after = Utc::now();
 
         assert_eq!(ISSUER, claims.issuer);
         assert_eq!(format!("{CUSTOMER_ID}:{VESSEL_ID}"), claims.user);
-        assert_eq!(AUDIENCE, claims.audience);
+        assert_eq!(Audience::from(AUDIENCE), claims.audience);
         assert!(claims.expires_at >= after.timestamp());
         assert!(claims.issued_at >= before.timestamp());
         assert!(claims.issued_at <= after.timestamp());
@@ -135,11 +448,341 @@ mod tests {
         let entry1 = claims.licenses.get(LICENSE_KEY_1);
         assert!(entry1.is_some());
         assert!(entry1.unwrap().count.is_none());
-        assert_eq!(Some(expires_at_1), entry1.unwrap().expires_at);
+        assert_eq!(Some(expires_at_1.into()), entry1.unwrap().expires_at);
 
         let entry2 = claims.licenses.get(LICENSE_KEY_2);
         assert!(entry2.is_some());
         assert!(entry2.unwrap().count.is_none());
         assert!(entry2.unwrap().expires_at.is_none());
+
+        assert_eq!(ClaimsVersion::V1, claims.version);
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn build_claims_from_filtered_licenses() {
+        let licenses = [LICENSE_KEY_0, LICENSE_KEY_1, LICENSE_KEY_2]
+            .into_iter()
+            .map(|license_key| LicenseFetchResponse {
+                license_key: license_key.to_string(),
+                count: None,
+                expires_at: None,
+                attributes: BTreeMap::new(),
+            })
+            .collect();
+
+        let claims = Claims::from_input(
+            licenses,
+            &LicenseFilter::patterns(vec!["ba*".to_string()]),
+            &CUSTOMER_ID,
+            &VESSEL_ID,
+            ISSUER.to_string(),
+            AUDIENCE.into(),
+            ExpiryFormat::Original,
+        );
+
+        assert_eq!(2, claims.licenses.len());
+        assert!(claims.licenses.contains_key(LICENSE_KEY_1));
+        assert!(claims.licenses.contains_key(LICENSE_KEY_2));
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn build_claims_with_allowed_attributes() {
+        let license = LicenseFetchResponse {
+            license_key: LICENSE_KEY_0.to_string(),
+            count: None,
+            expires_at: None,
+            attributes: BTreeMap::from([("tier".to_string(), json!("gold")), ("price".to_string(), json!(100))]),
+        };
+
+        let claims = Claims::from_input(
+            vec![license],
+            &LicenseFilter::all().with_attributes(["tier".to_string()]),
+            &CUSTOMER_ID,
+            &VESSEL_ID,
+            ISSUER.to_string(),
+            AUDIENCE.into(),
+            ExpiryFormat::Original,
+        );
+
+        let attributes = &claims.licenses[LICENSE_KEY_0].attributes;
+        assert_eq!(1, attributes.len());
+        assert_eq!(Some(&json!("gold")), attributes.get("tier"));
+        assert!(to_string(&claims)
+            .unwrap()
+            .contains("\"attributes\":{\"tier\":\"gold\"}"));
+    }
+
+    #[cfg(feature = "lambda")]
+    fn license_claims(expiry_format: ExpiryFormat) -> Claims {
+        Claims::from_input(
+            vec![LicenseFetchResponse {
+                license_key: LICENSE_KEY_0.to_string(),
+                count: Some(COUNT_0),
+                expires_at: Some(
+                    Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0)
+                        .unwrap()
+                        .with_timezone(&FixedOffset::east_opt(3600).unwrap()),
+                ),
+                attributes: BTreeMap::new(),
+            }],
+            &LicenseFilter::all(),
+            &CUSTOMER_ID,
+            &VESSEL_ID,
+            ISSUER.to_string(),
+            AUDIENCE.into(),
+            expiry_format,
+        )
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn serialize_original_expiry() {
+        let output = to_string(&license_claims(ExpiryFormat::Original)).unwrap();
+
+        assert!(output.contains("\"expiresAt\":\"2011-01-30T14:58:00+01:00\""));
+        assert!(!output.contains("\"ivms:ver\""));
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn serialize_utc_expiry() {
+        let output = to_string(&license_claims(ExpiryFormat::Utc)).unwrap();
+
+        assert!(output.contains("\"expiresAt\":\"2011-01-30T13:58:00Z\""));
+        assert!(!output.contains("\"ivms:ver\""));
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn serialize_timestamp_expiry() {
+        let output = to_string(&license_claims(ExpiryFormat::Timestamp)).unwrap();
+
+        assert!(output.contains("\"expiresAt\":1296395880"));
+        assert!(output.contains("\"ivms:ver\":2"));
+    }
+
+    fn frozen_claims() -> Claims {
+        Claims {
+            issuer: ISSUER.to_string(),
+            user: format!("{CUSTOMER_ID}:{VESSEL_ID}"),
+            minion_id: None,
+            audience: AUDIENCE.into(),
+            expires_at: 1359467880,
+            issued_at: 1296395880,
+            licenses: HashMap::from([(
+                LICENSE_KEY_0.to_string(),
+                LicenseClaim {
+                    count: Some(COUNT_0),
+                    expires_at: Some(LicenseExpiry::Timestamp(1296395880)),
+                    attributes: BTreeMap::new(),
+                },
+            )]),
+            inventory: vec![],
+            version: ClaimsVersion::V2,
+        }
+    }
+
+    const FROZEN_V1: &str = concat!(
+        r#"{"iss":"ivms-salt-executor","#,
+        r#""sub":"00000000-0000-0000-0000-000000000000:00000000-0000-0000-0000-000000000001","#,
+        r#""aud":"test","exp":1359467880,"iat":1296395880,"#,
+        r#""ivms:licenses":{"foo":{"count":12,"expiresAt":"2011-01-30T13:58:00Z"}}}"#,
+    );
+    const FROZEN_V2: &str = concat!(
+        r#"{"iss":"ivms-salt-executor","#,
+        r#""sub":"00000000-0000-0000-0000-000000000000:00000000-0000-0000-0000-000000000001","#,
+        r#""aud":"test","exp":1359467880,"iat":1296395880,"#,
+        r#""ivms:licenses":{"foo":{"count":12,"expiresAt":1296395880}},"#,
+        r#""ivms:ver":2}"#,
+    );
+
+    #[test]
+    fn serialize_frozen_v1() {
+        assert_eq!(
+            FROZEN_V1,
+            to_string(&frozen_claims().with_version(ClaimsVersion::V1)).unwrap()
+        );
+    }
+
+    #[test]
+    fn serialize_frozen_v2() {
+        assert_eq!(
+            FROZEN_V2,
+            to_string(&frozen_claims().with_version(ClaimsVersion::V2)).unwrap()
+        );
+    }
+
+    #[test]
+    fn convert_between_versions() {
+        let claims = frozen_claims()
+            .with_version(ClaimsVersion::V1)
+            .with_version(ClaimsVersion::V2);
+
+        assert_eq!(FROZEN_V2, to_string(&claims).unwrap());
+    }
+
+    #[test]
+    fn deserialize_versions() {
+        let v1: Claims = from_str(FROZEN_V1).unwrap();
+        let v2: Claims = from_str(FROZEN_V2).unwrap();
+
+        assert_eq!(ClaimsVersion::V1, v1.version);
+        assert_eq!(ClaimsVersion::V2, v2.version);
+        assert_eq!(v1.licenses, v2.licenses);
+    }
+
+    #[test]
+    fn deserialize_explicit_v1_claims() {
+        let claims: Claims = from_str(&FROZEN_V1.replace("}}}", r#"}},"ivms:ver":1}"#)).unwrap();
+
+        assert_eq!(ClaimsVersion::V1, claims.version);
+    }
+
+    #[test]
+    fn serialize_v1_as_unversioned_baseline() {
+        // layout emitted before the claims were versioned
+        const BASELINE: &str = concat!(
+            r#"{"iss":"ivms-salt-executor","#,
+            r#""sub":"00000000-0000-0000-0000-000000000000:00000000-0000-0000-0000-000000000001","#,
+            r#""aud":"test","exp":1359467880,"iat":1296395880,"#,
+            r#""ivms:licenses":{"foo":{"count":null,"expiresAt":"2011-01-30T14:58:00+01:00"}}}"#,
+        );
+
+        let claims = Claims {
+            licenses: HashMap::from([(
+                LICENSE_KEY_0.to_string(),
+                LicenseClaim {
+                    count: None,
+                    expires_at: Some(LicenseExpiry::DateTime(
+                        Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0)
+                            .unwrap()
+                            .with_timezone(&FixedOffset::east_opt(3600).unwrap()),
+                    )),
+                    attributes: BTreeMap::new(),
+                },
+            )]),
+            version: ClaimsVersion::V1,
+            ..frozen_claims()
+        };
+
+        assert_eq!(BASELINE, to_string(&claims).unwrap());
+    }
+
+    #[test]
+    fn deserialize_unknown_version() {
+        assert!(from_str::<Claims>(&FROZEN_V2.replace(r#""ivms:ver":2"#, r#""ivms:ver":3"#)).is_err());
+    }
+
+    #[test]
+    fn serialize_multiple_audiences() {
+        let claims = Claims {
+            audience: Audience::Multiple(vec!["salt".to_string(), "ivms".to_string()]),
+            ..frozen_claims()
+        };
+
+        assert!(to_string(&claims).unwrap().contains("\"aud\":[\"salt\",\"ivms\"]"));
+    }
+
+    #[test]
+    fn serialize_single_element_audience() {
+        let claims = Claims {
+            audience: Audience::Multiple(vec!["salt".to_string()]),
+            ..frozen_claims()
+        };
+
+        assert!(to_string(&claims).unwrap().contains("\"aud\":\"salt\""));
+    }
+
+    #[test]
+    fn serialize_minion_id() {
+        let claims = Claims {
+            minion_id: Some("node-1".to_string()),
+            ..frozen_claims()
+        };
+
+        assert!(to_string(&claims).unwrap().contains("\"ivms:minion\":\"node-1\""));
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn serialize_inventory_without_secrets() {
+        let claims = Claims {
+            inventory: vec![InventoryClaim::from(InventoryFetchResponse {
+                inventory_type: "jwt_key".to_string(),
+                inventory_id: "node-1".to_string(),
+                serial_number: Some("secret".into()),
+                aws_instance_id: Some("i-0123".to_string()),
+                created_at: Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0).unwrap().fixed_offset(),
+            })],
+            ..frozen_claims()
+        };
+
+        let output = to_string(&claims).unwrap();
+
+        assert!(output.contains(concat!(
+            r#""ivms:inventory":[{"inventoryType":"jwt_key","inventoryId":"node-1","#,
+            r#""createdAt":"2011-01-30T13:58:00Z","awsInstanceId":"i-0123"}]"#,
+        )));
+        assert!(!output.contains("secret"));
+    }
+
+    #[test]
+    fn deserialize_audience() {
+        let single: Audience = from_str("\"salt\"").unwrap();
+        let multiple: Audience = from_str("[\"salt\",\"ivms\"]").unwrap();
+
+        assert!(single.contains("salt"));
+        assert!(!single.contains("ivms"));
+        assert!(multiple.contains("salt"));
+        assert!(multiple.contains("ivms"));
+        assert!(!multiple.contains("other"));
+    }
+
+    #[test]
+    fn deserialize_single_element_audience() {
+        assert_eq!(
+            Audience::Single("salt".to_string()),
+            from_str::<Audience>("[\"salt\"]").unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_empty_audience() {
+        assert!(from_str::<Audience>("[]").is_err());
+    }
+
+    #[test]
+    fn deserialize_license_expiry() {
+        let timestamp: LicenseExpiry = from_str("1296395880").unwrap();
+        let date: LicenseExpiry = from_str("\"2011-01-30T14:58:00+01:00\"").unwrap();
+
+        assert!(matches!(timestamp, LicenseExpiry::Timestamp(_)));
+        assert!(matches!(date, LicenseExpiry::DateTime(_)));
+        assert_eq!(timestamp, date);
+        assert_eq!(Utc.with_ymd_and_hms(2011, 1, 30, 13, 58, 0).unwrap(), date.to_utc());
+    }
+
+    #[test]
+    #[cfg(feature = "lambda")]
+    fn parse_subject() {
+        let claims = Claims::from_input(
+            vec![],
+            &LicenseFilter::all(),
+            &CUSTOMER_ID,
+            &VESSEL_ID,
+            ISSUER.to_string(),
+            AUDIENCE.into(),
+            ExpiryFormat::Original,
+        );
+
+        assert_eq!((CUSTOMER_ID, VESSEL_ID), claims.subject().unwrap());
+        assert!(Claims {
+            user: "foo".to_string(),
+            ..claims
+        }
+        .subject()
+        .is_err());
     }
 }
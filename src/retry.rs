@@ -0,0 +1,111 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Retry policy of downstream invocations.
+
+use aws_sdk_lambda::operation::invoke::InvokeError;
+use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+use aws_smithy_runtime_api::client::result::SdkError;
+use std::cmp::min;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub invoke_timeout: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+            invoke_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // "full jitter" - random delay up to exponentially growing cap
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let cap = min(
+            self.max_delay,
+            self.base_delay
+                .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))),
+        );
+
+        Duration::from_millis(fastrand::u64(..=cap.as_millis() as u64))
+    }
+}
+
+pub fn is_transient(error: &SdkError<InvokeError, HttpResponse>) -> bool {
+    match error {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => true,
+        SdkError::ServiceError(context) => matches!(
+            context.err(),
+            InvokeError::TooManyRequestsException(_)
+                | InvokeError::Ec2ThrottledException(_)
+                | InvokeError::ServiceException(_)
+                | InvokeError::ResourceNotReadyException(_)
+        ),
+        _ => false,
+    }
+}
+
+// Lambda context provides deadline as epoch milliseconds, margin leaves time to report the failure
+pub fn deadline_from_epoch_millis(deadline: u64, margin: Duration) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+
+    Instant::now() + Duration::from_millis(deadline.saturating_sub(now)).saturating_sub(margin)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retry::{deadline_from_epoch_millis, RetryPolicy};
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    #[test]
+    fn backoff_within_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+            invoke_timeout: Duration::from_secs(1),
+        };
+
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+            assert!(policy.backoff(2) <= Duration::from_millis(200));
+            assert!(policy.backoff(3) <= Duration::from_millis(300));
+            assert!(policy.backoff(30) <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn deadline_in_future() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        let before = Instant::now();
+        let deadline = deadline_from_epoch_millis(now + 10_000, Duration::from_secs(1));
+
+        assert!(deadline > before + Duration::from_secs(8));
+        assert!(deadline <= Instant::now() + Duration::from_secs(9));
+    }
+
+    #[test]
+    fn deadline_in_past() {
+        let before = Instant::now();
+        let deadline = deadline_from_epoch_millis(0, Duration::from_secs(1));
+
+        assert!(deadline >= before);
+        assert!(deadline <= Instant::now());
+    }
+}
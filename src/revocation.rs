@@ -0,0 +1,77 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Revocation of issued tokens.
+
+use crate::model::Claims;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Instants by subject (`customer:vessel`) - tokens of the subject issued at or before the instant are revoked, eg.
+/// after the vessel hardware was replaced or decommissioned.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(transparent)]
+pub struct Revocations(HashMap<String, DateTime<Utc>>);
+
+impl Revocations {
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        self.0
+            .get(&claims.user)
+            .is_some_and(|revoked_at| claims.issued_at <= revoked_at.timestamp())
+    }
+}
+
+impl FromIterator<(String, DateTime<Utc>)> for Revocations {
+    fn from_iter<I: IntoIterator<Item = (String, DateTime<Utc>)>>(revocations: I) -> Self {
+        Self(revocations.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{Claims, ClaimsVersion};
+    use crate::revocation::Revocations;
+    use chrono::{TimeZone, Utc};
+    use serde_json::from_str;
+    use std::collections::HashMap;
+
+    const SUBJECT: &str = "00000000-0000-0000-0000-000000000000:00000000-0000-0000-0000-000000000001";
+    const REVOKED_AT: i64 = 1_000_000;
+
+    fn claims(user: &str, issued_at: i64) -> Claims {
+        Claims {
+            issuer: "unit-test".to_string(),
+            user: user.to_string(),
+            minion_id: None,
+            audience: "local".into(),
+            expires_at: issued_at + 1000,
+            issued_at,
+            licenses: HashMap::new(),
+            inventory: vec![],
+            version: ClaimsVersion::V1,
+        }
+    }
+
+    #[test]
+    fn revoke_tokens_issued_before() {
+        let revocations = [(SUBJECT.to_string(), Utc.timestamp_opt(REVOKED_AT, 0).unwrap())]
+            .into_iter()
+            .collect::<Revocations>();
+
+        assert!(revocations.is_revoked(&claims(SUBJECT, REVOKED_AT - 10)));
+        assert!(revocations.is_revoked(&claims(SUBJECT, REVOKED_AT)));
+        assert!(!revocations.is_revoked(&claims(SUBJECT, REVOKED_AT + 10)));
+        assert!(!revocations.is_revoked(&claims("other:subject", REVOKED_AT - 10)));
+    }
+
+    #[test]
+    fn deserialize_revocations() {
+        let revocations: Revocations = from_str(&format!("{{\"{SUBJECT}\":\"1970-01-12T13:46:40Z\"}}")).unwrap();
+
+        assert!(revocations.is_revoked(&claims(SUBJECT, REVOKED_AT)));
+    }
+}
@@ -4,26 +4,60 @@
  * @copyright 2023 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
+//! Service errors.
+
+use argon2::Error as KeyDerivationError;
+#[cfg(feature = "lambda")]
 use aws_sdk_lambda::operation::invoke::InvokeError;
+#[cfg(feature = "lambda")]
 use aws_smithy_runtime_api::client::orchestrator::HttpResponse;
+#[cfg(feature = "lambda")]
 use aws_smithy_runtime_api::client::result::SdkError;
+use ed25519_dalek::pkcs8::Error as KeyEncodingError;
 use hmac::digest::InvalidLength;
 use jwt::Error as JwtError;
 use serde_json::Error as SerializationError;
-use std::env::VarError;
 use std::fmt::{Debug, Display, Formatter, Result};
+use std::io::Error as IoError;
 use thiserror::Error;
 use uuid::Error as UuidError;
 
 #[derive(Error, Debug)]
 pub enum RuntimeError {
-    ClientConfigLoadingError(VarError),
-    LambdaInvokeError(#[from] SdkError<InvokeError, HttpResponse>),
+    InvalidConfig(Vec<String>),
+    UnknownHandler(String),
+    #[cfg(feature = "lambda")]
+    // boxed, as it would make every result of the crate several hundred bytes large
+    LambdaInvokeError(Box<SdkError<InvokeError, HttpResponse>>),
+    InvokeTimeout,
+    DeadlineExceeded,
+    MissingInventoryKey,
     MissingKey,
+    MissingInventory(String, String),
+    UnknownRole(String),
     InvalidKey(#[from] InvalidLength),
+    WeakKey(Vec<String>),
+    KeyDerivationError(#[from] KeyDerivationError),
+    InvalidServiceKey(KeyEncodingError),
+    ServiceKeyUnavailable(String),
+    NoActiveServiceKey,
     JwtError(#[from] JwtError),
     SerializationError(#[from] SerializationError),
     UuidError(#[from] UuidError),
+    HardwareIdError(IoError),
+    MalformedToken,
+    SubjectMismatch,
+    KdfMismatch,
+    MissingCountersignature,
+    UnknownServiceKey(String),
+    InvalidCountersignature,
+    UnsupportedJwk(String),
+    TokenExpired,
+    TokenRevoked,
+    TokenNotYetValid,
+    InvalidIssuer,
+    InvalidAudience,
+    InvalidMinion,
 }
 
 impl Display for RuntimeError {
@@ -31,3 +65,51 @@ impl Display for RuntimeError {
         write!(formatter, "{self:?}")
     }
 }
+
+#[cfg(feature = "lambda")]
+impl From<SdkError<InvokeError, HttpResponse>> for RuntimeError {
+    fn from(error: SdkError<InvokeError, HttpResponse>) -> Self {
+        Self::LambdaInvokeError(Box::new(error))
+    }
+}
+
+impl RuntimeError {
+    /// Variant name, stable across releases - used as outcome dimension of metrics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidConfig(_) => "InvalidConfig",
+            Self::UnknownHandler(_) => "UnknownHandler",
+            #[cfg(feature = "lambda")]
+            Self::LambdaInvokeError(_) => "LambdaInvokeError",
+            Self::InvokeTimeout => "InvokeTimeout",
+            Self::DeadlineExceeded => "DeadlineExceeded",
+            Self::MissingInventoryKey => "MissingInventoryKey",
+            Self::MissingKey => "MissingKey",
+            Self::MissingInventory(_, _) => "MissingInventory",
+            Self::UnknownRole(_) => "UnknownRole",
+            Self::InvalidKey(_) => "InvalidKey",
+            Self::WeakKey(_) => "WeakKey",
+            Self::KeyDerivationError(_) => "KeyDerivationError",
+            Self::InvalidServiceKey(_) => "InvalidServiceKey",
+            Self::ServiceKeyUnavailable(_) => "ServiceKeyUnavailable",
+            Self::NoActiveServiceKey => "NoActiveServiceKey",
+            Self::JwtError(_) => "JwtError",
+            Self::SerializationError(_) => "SerializationError",
+            Self::UuidError(_) => "UuidError",
+            Self::HardwareIdError(_) => "HardwareIdError",
+            Self::MalformedToken => "MalformedToken",
+            Self::SubjectMismatch => "SubjectMismatch",
+            Self::KdfMismatch => "KdfMismatch",
+            Self::MissingCountersignature => "MissingCountersignature",
+            Self::UnknownServiceKey(_) => "UnknownServiceKey",
+            Self::InvalidCountersignature => "InvalidCountersignature",
+            Self::UnsupportedJwk(_) => "UnsupportedJwk",
+            Self::TokenExpired => "TokenExpired",
+            Self::TokenRevoked => "TokenRevoked",
+            Self::TokenNotYetValid => "TokenNotYetValid",
+            Self::InvalidIssuer => "InvalidIssuer",
+            Self::InvalidAudience => "InvalidAudience",
+            Self::InvalidMinion => "InvalidMinion",
+        }
+    }
+}
@@ -0,0 +1,69 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Key material handling.
+
+use serde::Deserialize;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use zeroize::Zeroize;
+
+/// Signing key (hardware identifier) - wiped from memory when dropped and never printed. Not cloneable, so that key
+/// material isn't copied around - share it through `Rc` instead.
+#[derive(Deserialize)]
+#[serde(transparent)]
+pub struct SecretKey(String);
+
+impl SecretKey {
+    /// Raw key material - only meant to be passed directly to the cryptographic primitives.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretKey {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretKey {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl Debug for SecretKey {
+    fn fmt(&self, formatter: &mut Formatter<'_>) -> FmtResult {
+        formatter.write_str("SecretKey(***)")
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secret::SecretKey;
+    use serde_json::from_str;
+
+    #[test]
+    fn redact_debug_output() {
+        let key = SecretKey::from("qwerta");
+
+        assert_eq!("SecretKey(***)", format!("{key:?}"));
+        assert_eq!("Some(SecretKey(***))", format!("{:?}", Some(key)));
+    }
+
+    #[test]
+    fn deserialize_from_string() {
+        let key: SecretKey = from_str("\"qwerta\"").unwrap();
+
+        assert_eq!("qwerta", key.expose());
+    }
+}
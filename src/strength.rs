@@ -0,0 +1,223 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Strength checks of the signing keys.
+
+use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+use tracing::warn;
+
+// values that firmware vendors put in place of real serial numbers
+const PLACEHOLDER_KEYS: [&str; 14] = [
+    "0",
+    "00000000",
+    "0123456789",
+    "123456789",
+    "chassis serial number",
+    "default string",
+    "invalid",
+    "n/a",
+    "none",
+    "not applicable",
+    "not specified",
+    "serial number",
+    "system serial number",
+    "to be filled by o.e.m.",
+];
+
+/// What to do with a key that doesn't pass the checks.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum WeakKeyAction {
+    /// Log a warning and sign the token anyway.
+    #[default]
+    Warn,
+    /// Fail with `WeakKey` error.
+    Reject,
+}
+
+impl FromStr for WeakKeyAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "warn" => Ok(Self::Warn),
+            "reject" => Ok(Self::Reject),
+            _ => Err(format!("unknown action {value}, expected warn or reject")),
+        }
+    }
+}
+
+pub struct KeyStrengthPolicy {
+    pub min_length: usize,
+    /// Minimum estimated entropy of the whole key, in bits.
+    pub min_entropy: f64,
+    /// Known placeholder values, compared case-insensitively.
+    pub denylist: BTreeSet<String>,
+    pub action: WeakKeyAction,
+}
+
+impl Default for KeyStrengthPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            min_entropy: 24.0,
+            denylist: PLACEHOLDER_KEYS.into_iter().map(String::from).collect(),
+            action: WeakKeyAction::default(),
+        }
+    }
+}
+
+impl KeyStrengthPolicy {
+    /// Describes all the problems found with the key - descriptions never contain the key itself.
+    pub fn weaknesses(&self, key: &SecretKey) -> Vec<String> {
+        let key = key.expose().trim();
+        let mut weaknesses = vec![];
+
+        if self
+            .denylist
+            .iter()
+            .any(|placeholder| placeholder.eq_ignore_ascii_case(key))
+        {
+            weaknesses.push("key is a known placeholder value".to_string());
+        }
+        if key.chars().count() < self.min_length {
+            weaknesses.push(format!("key is shorter than {} characters", self.min_length));
+        }
+        if entropy(key) < self.min_entropy {
+            weaknesses.push(format!("key entropy is below {} bits", self.min_entropy));
+        }
+
+        weaknesses
+    }
+
+    /// Applies the policy to the key - weak key is either reported in logs or rejected.
+    pub fn check(&self, key: &SecretKey) -> Result<(), RuntimeError> {
+        let weaknesses = self.weaknesses(key);
+
+        match self.action {
+            _ if weaknesses.is_empty() => Ok(()),
+            WeakKeyAction::Warn => {
+                warn!(?weaknesses, "signing key is weak");
+                Ok(())
+            }
+            WeakKeyAction::Reject => Err(RuntimeError::WeakKey(weaknesses)),
+        }
+    }
+}
+
+// Shannon entropy of the characters distribution, summed over the characters that can't be guessed from the preceding
+// ones - rough estimate, but good enough to catch repeated characters, short alphabets and sequential runs like `1234`
+fn entropy(key: &str) -> f64 {
+    let mut counts = HashMap::new();
+    for character in key.chars() {
+        *counts.entry(character).or_insert(0) += 1;
+    }
+
+    let length = key.chars().count() as f64;
+    let unpredictable = length - predictable(key) as f64;
+    counts
+        .values()
+        .map(|count| {
+            let probability = *count as f64 / length;
+            -probability * probability.log2() * unpredictable
+        })
+        .sum()
+}
+
+// characters continuing a run of repeated, ascending or descending characters (`aaaa`, `1234`, `dcba`)
+fn predictable(key: &str) -> usize {
+    let codes = key.chars().map(|character| character as i64).collect::<Vec<_>>();
+
+    codes
+        .windows(3)
+        .filter(|window| {
+            let step = window[1] - window[0];
+            step.abs() <= 1 && window[2] - window[1] == step
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime_error::RuntimeError;
+    use crate::strength::{KeyStrengthPolicy, WeakKeyAction};
+
+    const STRONG_KEY: &str = "i-0a1b2c3d4e5f67890";
+
+    fn reject() -> KeyStrengthPolicy {
+        KeyStrengthPolicy {
+            action: WeakKeyAction::Reject,
+            ..KeyStrengthPolicy::default()
+        }
+    }
+
+    #[test]
+    fn accept_strong_key() {
+        assert!(KeyStrengthPolicy::default().weaknesses(&STRONG_KEY.into()).is_empty());
+        assert!(reject().check(&STRONG_KEY.into()).is_ok());
+    }
+
+    #[test]
+    fn detect_short_key() {
+        assert_eq!(
+            vec![
+                "key is shorter than 8 characters".to_string(),
+                "key entropy is below 24 bits".to_string(),
+            ],
+            KeyStrengthPolicy::default().weaknesses(&"x9Z2".into())
+        );
+    }
+
+    #[test]
+    fn detect_low_entropy_key() {
+        assert_eq!(
+            vec!["key entropy is below 24 bits".to_string()],
+            KeyStrengthPolicy::default().weaknesses(&"aaaaaaaaaaaaaaab".into())
+        );
+    }
+
+    #[test]
+    fn detect_sequential_key() {
+        assert_eq!(
+            vec!["key entropy is below 24 bits".to_string()],
+            KeyStrengthPolicy::default().weaknesses(&"12345678".into())
+        );
+        assert_eq!(
+            vec!["key entropy is below 24 bits".to_string()],
+            KeyStrengthPolicy::default().weaknesses(&"hgfedcba".into())
+        );
+    }
+
+    #[test]
+    fn detect_placeholder_key() {
+        let weaknesses = KeyStrengthPolicy::default().weaknesses(&" To Be Filled By O.E.M. ".into());
+
+        assert_eq!("key is a known placeholder value", weaknesses[0]);
+    }
+
+    #[test]
+    fn warn_about_weak_key() {
+        assert!(KeyStrengthPolicy::default().check(&"1234".into()).is_ok());
+    }
+
+    #[test]
+    fn reject_weak_key() {
+        assert!(matches!(
+            reject().check(&"1234".into()),
+            Err(RuntimeError::WeakKey(weaknesses)) if weaknesses.len() == 2
+        ));
+    }
+
+    #[test]
+    fn parse_action() {
+        assert_eq!(Ok(WeakKeyAction::Reject), "reject".parse());
+        assert!("ignore".parse::<WeakKeyAction>().is_err());
+    }
+}
@@ -0,0 +1,101 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! OpenTelemetry tracing of the service and propagation of trace context to downstream services.
+
+use opentelemetry::propagation::TextMapPropagator;
+use opentelemetry::trace::TraceError;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{new_exporter, new_pipeline, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::runtime::Tokio;
+use opentelemetry_sdk::trace::{config, Tracer, TracerProvider};
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use tokio::task::spawn_blocking;
+use tracing::{warn, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SERVICE_NAME: &str = "ivms-salt-extractor";
+
+// global provider only exposes the API, flushing needs the SDK one
+static PROVIDER: OnceLock<TracerProvider> = OnceLock::new();
+
+/// Builds tracer exporting spans over OTLP (gRPC) to the collector at given endpoint (eg. `http://localhost:4317`).
+pub fn otlp_tracer(endpoint: &str) -> Result<Tracer, TraceError> {
+    let tracer = new_pipeline()
+        .tracing()
+        .with_exporter(new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(config().with_resource(Resource::new(vec![KeyValue::new("service.name", SERVICE_NAME)])))
+        .install_batch(Tokio)?;
+
+    if let Some(provider) = tracer.provider() {
+        // only the first installed provider is flushed, there is just one per process anyway
+        let _ = PROVIDER.set(provider);
+    }
+
+    Ok(tracer)
+}
+
+/// Exports spans buffered by the batch processor - Lambda may freeze the environment right after the invocation, before
+/// the scheduled export happens. Does nothing when spans are not exported.
+pub async fn flush_spans() {
+    let Some(provider) = PROVIDER.get().cloned() else {
+        return;
+    };
+
+    // flushing blocks until the export is done, runtime workers must stay free to run it
+    match spawn_blocking(move || provider.force_flush()).await {
+        Ok(results) => {
+            for error in results.into_iter().filter_map(Result::err) {
+                warn!(%error, "spans export failed");
+            }
+        }
+        Err(error) => warn!(%error, "spans export failed"),
+    }
+}
+
+/// W3C trace context of the current span, to be passed along with the downstream request - empty when spans are not
+/// exported.
+pub fn current_trace_context() -> HashMap<String, String> {
+    let mut carrier = HashMap::new();
+    TraceContextPropagator::new().inject_context(&Span::current().context(), &mut carrier);
+    carrier
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::telemetry::current_trace_context;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing::info_span;
+    use tracing::subscriber::with_default;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Registry;
+
+    #[test]
+    fn propagate_span_context() {
+        let provider = TracerProvider::builder().build();
+        let tracer = provider.tracer("test");
+        let subscriber = Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        let context = with_default(subscriber, || {
+            let _span = info_span!("fetch_inventory").entered();
+
+            current_trace_context()
+        });
+
+        assert!(context["traceparent"].starts_with("00-"));
+    }
+
+    #[test]
+    fn skip_context_without_tracing() {
+        let _span = info_span!("fetch_inventory").entered();
+
+        assert!(current_trace_context().is_empty());
+    }
+}
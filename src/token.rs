@@ -0,0 +1,310 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Signing and decoding of license tokens.
+
+use crate::countersign::inner_token;
+use crate::kdf::{Kdf, KeyStretching};
+use crate::model::Claims;
+use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
+use hmac::digest::KeyInit;
+use hmac::Hmac;
+use jwt::{AlgorithmType, Header, JoseHeader, SignWithKey, Token, VerifyWithKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+
+/// JOSE header extended with parameters of the key derivation, if the signing key was stretched.
+#[derive(Default, Deserialize, Serialize)]
+pub struct TokenHeader {
+    #[serde(flatten)]
+    pub header: Header,
+    #[serde(rename = "ivms:kdf", default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<Kdf>,
+}
+
+impl JoseHeader for TokenHeader {
+    fn algorithm_type(&self) -> AlgorithmType {
+        self.header.algorithm
+    }
+}
+
+/// Builds HMAC key from the hardware identifier (`jwt_key` inventory serial number).
+pub fn signing_key(key: &SecretKey) -> Result<Hmac<Sha512>, RuntimeError> {
+    Hmac::new_from_slice(key.expose().as_bytes()).map_err(RuntimeError::from)
+}
+
+// key derivation is salted with (digest of, to fit salt length constraints) the token subject - customer and vessel ids
+fn stretched_signing_key(
+    key: &SecretKey,
+    kdf: &Kdf,
+    claims: &Claims,
+    pepper: Option<&SecretKey>,
+) -> Result<Hmac<Sha512>, RuntimeError> {
+    Hmac::new_from_slice(&kdf.derive(key, &Sha512::digest(claims.user.as_bytes()), pepper)?).map_err(RuntimeError::from)
+}
+
+/// Signs claims with the hardware identifier, producing compact JWT. When stretching is given, signing key is derived
+/// from the identifier and derivation parameters are recorded in the header.
+pub fn sign_claims(
+    claims: &Claims,
+    key: &SecretKey,
+    stretching: Option<&KeyStretching>,
+) -> Result<String, RuntimeError> {
+    let (kdf, key) = match stretching {
+        Some(stretching) => (
+            Some(stretching.kdf),
+            stretched_signing_key(key, &stretching.kdf, claims, stretching.pepper.as_deref())?,
+        ),
+        None => (None, signing_key(key)?),
+    };
+    let header = TokenHeader {
+        header: Header {
+            algorithm: AlgorithmType::Hs512,
+            ..Header::default()
+        },
+        kdf,
+    };
+
+    Ok(Token::new(header, claims).sign_with_key(&key)?.as_str().to_string())
+}
+
+/// Checks token signature against the hardware identifier and returns its claims. Token has to be stretched whenever the
+/// service stretches keys - derivation parameters recorded in the header are only used within the bounds of the
+/// stretching, as the header is not verified before the key is derived (and any parameters would let the token sender
+/// pick the derivation cost).
+///
+/// Only the signature is checked here - time based claims are up to the caller.
+pub fn verify_signature(
+    token: &str,
+    key: &SecretKey,
+    stretching: Option<&KeyStretching>,
+) -> Result<Claims, RuntimeError> {
+    let token: Token<TokenHeader, Claims, _> = Token::parse_unverified(token)?;
+    let key = match (&token.header().kdf, stretching) {
+        (None, None) => signing_key(key)?,
+        (Some(kdf), Some(stretching)) if stretching.bounds.contains(kdf) => {
+            stretched_signing_key(key, kdf, token.claims(), stretching.pepper.as_deref())?
+        }
+        _ => return Err(RuntimeError::KdfMismatch),
+    };
+    let (_, claims) = token.verify_with_key(&key)?.into();
+
+    Ok(claims)
+}
+
+/// Reads claims without checking the signature (nor the countersignature) - meant only for inspecting tokens issued by
+/// this service.
+pub fn decode_claims(token: &str) -> Result<Claims, RuntimeError> {
+    let token = inner_token(token)?;
+    let token: Token<TokenHeader, Claims, _> = Token::parse_unverified(&token)?;
+    let (_, claims) = token.into();
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kdf::{Kdf, KdfBounds, KeyStretching};
+    use crate::model::{Claims, ClaimsVersion};
+    use crate::runtime_error::RuntimeError;
+    use crate::secret::SecretKey;
+    use crate::token::{decode_claims, sign_claims, signing_key, verify_signature, TokenHeader};
+    use jwt::{AlgorithmType, Header, SignWithKey, Token};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    const KEY: &str = "qwerta";
+    const OTHER_KEY: &str = "qwertp";
+    const ISSUER: &str = "unit-test";
+    const AUDIENCE: &str = "local";
+    const SUBJECT: &str = "foo:bar";
+
+    fn claims() -> Claims {
+        Claims {
+            issuer: ISSUER.to_string(),
+            user: SUBJECT.to_string(),
+            minion_id: None,
+            audience: AUDIENCE.into(),
+            expires_at: 2000,
+            issued_at: 1000,
+            licenses: HashMap::new(),
+            inventory: vec![],
+            version: ClaimsVersion::V1,
+        }
+    }
+
+    fn stretching(pepper: Option<&str>) -> KeyStretching {
+        let kdf = Kdf::Argon2id {
+            memory: 64,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        KeyStretching {
+            kdf,
+            bounds: kdf.into(),
+            pepper: pepper.map(|pepper| Rc::new(pepper.into())),
+        }
+    }
+
+    #[test]
+    fn sign_and_verify() {
+        let token = sign_claims(&claims(), &KEY.into(), None).unwrap();
+        let claims = verify_signature(&token, &SecretKey::from(KEY), None).unwrap();
+
+        assert_eq!(ISSUER, claims.issuer);
+        assert_eq!(SUBJECT, claims.user);
+        assert!(claims.audience.contains(AUDIENCE));
+        assert_eq!(2000, claims.expires_at);
+        assert_eq!(1000, claims.issued_at);
+    }
+
+    #[test]
+    fn verify_with_other_key() {
+        let token = sign_claims(&claims(), &KEY.into(), None).unwrap();
+
+        assert!(verify_signature(&token, &OTHER_KEY.into(), None).is_err());
+    }
+
+    #[test]
+    fn sign_with_plain_header() {
+        let token = sign_claims(&claims(), &KEY.into(), None).unwrap();
+        let token: Token<Header, Claims, _> = Token::parse_unverified(&token).unwrap();
+
+        assert_eq!(None, token.header().type_);
+        assert_eq!(None, token.header().key_id);
+    }
+
+    #[test]
+    fn sign_and_verify_stretched() {
+        let token = sign_claims(&claims(), &KEY.into(), Some(&stretching(Some("pepper")))).unwrap();
+        let header: Token<TokenHeader, Claims, _> = Token::parse_unverified(&token).unwrap();
+
+        assert_eq!(Some(stretching(None).kdf), header.header().kdf);
+        assert_eq!(
+            SUBJECT,
+            verify_signature(&token, &KEY.into(), Some(&stretching(Some("pepper"))))
+                .unwrap()
+                .user
+        );
+        assert!(verify_signature(&token, &KEY.into(), Some(&stretching(None))).is_err());
+        assert!(verify_signature(&token, &KEY.into(), Some(&stretching(Some("other")))).is_err());
+        assert!(verify_signature(&token, &OTHER_KEY.into(), Some(&stretching(Some("pepper")))).is_err());
+    }
+
+    #[test]
+    fn reject_unexpected_kdf() {
+        let stretched = sign_claims(&claims(), &KEY.into(), Some(&stretching(None))).unwrap();
+        let plain = sign_claims(&claims(), &KEY.into(), None).unwrap();
+
+        assert!(matches!(
+            verify_signature(&stretched, &KEY.into(), None),
+            Err(RuntimeError::KdfMismatch)
+        ));
+        // stripping the header must not downgrade to the raw hardware identifier
+        assert!(matches!(
+            verify_signature(&plain, &KEY.into(), Some(&stretching(None))),
+            Err(RuntimeError::KdfMismatch)
+        ));
+    }
+
+    #[test]
+    fn reject_oversized_kdf_parameters() {
+        // would allocate 4 TiB if derived
+        let header = TokenHeader {
+            header: Header {
+                algorithm: AlgorithmType::Hs512,
+                ..Header::default()
+            },
+            kdf: Some(Kdf::Argon2id {
+                memory: u32::MAX,
+                iterations: 1,
+                parallelism: 1,
+            }),
+        };
+        let token = Token::new(header, claims())
+            .sign_with_key(&signing_key(&KEY.into()).unwrap())
+            .unwrap();
+
+        assert!(matches!(
+            verify_signature(token.as_str(), &KEY.into(), Some(&stretching(None))),
+            Err(RuntimeError::KdfMismatch)
+        ));
+    }
+
+    #[test]
+    fn verify_after_kdf_rotation() {
+        let issued = sign_claims(&claims(), &KEY.into(), Some(&stretching(None))).unwrap();
+        let rotated = KeyStretching {
+            kdf: Kdf::Argon2id {
+                memory: 128,
+                iterations: 2,
+                parallelism: 1,
+            },
+            bounds: KdfBounds {
+                min: stretching(None).kdf,
+                max: Kdf::Argon2id {
+                    memory: 256,
+                    iterations: 2,
+                    parallelism: 1,
+                },
+            },
+            pepper: None,
+        };
+        let reissued = sign_claims(&claims(), &KEY.into(), Some(&rotated)).unwrap();
+
+        // tokens issued with both old and new parameters are valid as long as they fall within the bounds
+        assert_eq!(
+            SUBJECT,
+            verify_signature(&issued, &KEY.into(), Some(&rotated)).unwrap().user
+        );
+        assert_eq!(
+            SUBJECT,
+            verify_signature(&reissued, &KEY.into(), Some(&rotated)).unwrap().user
+        );
+        assert!(matches!(
+            verify_signature(&reissued, &KEY.into(), Some(&stretching(None))),
+            Err(RuntimeError::KdfMismatch)
+        ));
+        assert!(matches!(
+            verify_signature(
+                &issued,
+                &KEY.into(),
+                Some(&KeyStretching {
+                    bounds: rotated.kdf.into(),
+                    ..rotated.clone()
+                })
+            ),
+            Err(RuntimeError::KdfMismatch)
+        ));
+    }
+
+    #[test]
+    fn stretched_key_bound_to_subject() {
+        let token = sign_claims(&claims(), &KEY.into(), Some(&stretching(None))).unwrap();
+        let forged = sign_claims(
+            &Claims {
+                user: "foo:baz".to_string(),
+                ..claims()
+            },
+            &KEY.into(),
+            Some(&stretching(None)),
+        )
+        .unwrap();
+
+        // same hardware identifier, but different subject yields different signature
+        assert_ne!(token.rsplit('.').next(), forged.rsplit('.').next());
+        assert!(verify_signature(&forged, &KEY.into(), Some(&stretching(None))).is_ok());
+    }
+
+    #[test]
+    fn decode_without_key() {
+        let token = sign_claims(&claims(), &KEY.into(), Some(&stretching(None))).unwrap();
+
+        assert_eq!(SUBJECT, decode_claims(&token).unwrap().user);
+    }
+}
@@ -0,0 +1,444 @@
+/*
+ * This file is part of the IVMS Online.
+ *
+ * @copyright 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
+ */
+
+//! Vessel-side token verification.
+
+use crate::countersign::{inner_token, key_id, verify_countersignature};
+use crate::jwks::Jwks;
+use crate::kdf::KeyStretching;
+use crate::model::Claims;
+use crate::runtime_error::RuntimeError;
+use crate::secret::SecretKey;
+use crate::token::verify_signature;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::VerifyingKey;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use zeroize::Zeroizing;
+
+const DMI_SERIAL_NUMBER_PATH: &str = "/sys/class/dmi/id/product_serial";
+const EC2_INSTANCE_ID_PATH: &str = "/var/lib/cloud/data/instance-id";
+
+/// Source of the hardware identifier that the token is signed with (the `jwt_key` inventory entry value).
+pub trait HardwareIdProvider {
+    fn hardware_id(&self) -> Result<Rc<SecretKey>, RuntimeError>;
+}
+
+impl<F: Fn() -> Result<Rc<SecretKey>, RuntimeError>> HardwareIdProvider for F {
+    fn hardware_id(&self) -> Result<Rc<SecretKey>, RuntimeError> {
+        self()
+    }
+}
+
+/// Fixed, already known hardware identifier.
+pub struct StaticHardwareId(pub Rc<SecretKey>);
+
+impl HardwareIdProvider for StaticHardwareId {
+    fn hardware_id(&self) -> Result<Rc<SecretKey>, RuntimeError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Hardware identifier read from a file, surrounding whitespace is ignored.
+pub struct FileHardwareId {
+    path: PathBuf,
+}
+
+impl FileHardwareId {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Machine serial number exposed by the kernel from DMI tables.
+    pub fn dmi_serial_number() -> Self {
+        Self::new(DMI_SERIAL_NUMBER_PATH)
+    }
+
+    /// EC2 instance id stored by cloud-init.
+    pub fn ec2_instance_id() -> Self {
+        Self::new(EC2_INSTANCE_ID_PATH)
+    }
+}
+
+impl HardwareIdProvider for FileHardwareId {
+    fn hardware_id(&self) -> Result<Rc<SecretKey>, RuntimeError> {
+        // raw content is wiped as well, not only the trimmed key
+        let content = Zeroizing::new(read_to_string(&self.path).map_err(RuntimeError::HardwareIdError)?);
+
+        Ok(Rc::new(content.trim().into()))
+    }
+}
+
+/// Verifies tokens against hardware identifier of the current machine.
+pub struct Verifier<P> {
+    provider: P,
+    leeway: Duration,
+    expiry_grace: Duration,
+    issuer: Option<String>,
+    audience: Option<String>,
+    minion_id: Option<String>,
+    stretching: Option<KeyStretching>,
+    service_keys: HashMap<String, VerifyingKey>,
+}
+
+impl<P: HardwareIdProvider> Verifier<P> {
+    pub fn new(provider: P) -> Self {
+        Self {
+            provider,
+            leeway: Duration::ZERO,
+            expiry_grace: Duration::ZERO,
+            issuer: None,
+            audience: None,
+            minion_id: None,
+            stretching: None,
+            service_keys: HashMap::new(),
+        }
+    }
+
+    /// Allowed clock skew between the issuing service and the vessel.
+    pub fn with_leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Accepts tokens expired within given time (on top of the leeway) - eg. for renewal, issue time is still checked
+    /// strictly.
+    pub fn with_expiry_grace(mut self, grace: Duration) -> Self {
+        self.expiry_grace = grace;
+        self
+    }
+
+    /// Requires `iss` claim to match.
+    pub fn with_issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Requires `aud` claim to contain given audience.
+    pub fn with_audience(mut self, audience: impl Into<String>) -> Self {
+        self.audience = Some(audience.into());
+        self
+    }
+
+    /// Requires token to be bound to given Salt minion.
+    pub fn with_minion_id(mut self, minion_id: impl Into<String>) -> Self {
+        self.minion_id = Some(minion_id.into());
+        self
+    }
+
+    /// Key stretching the service signs tokens with - tokens not stretched or stretched with parameters outside of its
+    /// bounds are rejected.
+    pub fn with_key_stretching(mut self, stretching: KeyStretching) -> Self {
+        self.stretching = Some(stretching);
+        self
+    }
+
+    /// Trusts given service key - once any is registered, tokens must be countersigned by one of them.
+    pub fn with_service_key(mut self, key: VerifyingKey) -> Self {
+        self.service_keys.insert(key_id(&key), key);
+        self
+    }
+
+    /// Trusts all keys of the service key set.
+    pub fn with_jwks(self, jwks: &Jwks) -> Result<Self, RuntimeError> {
+        jwks.keys.iter().try_fold(
+            self,
+            |verifier, jwk| Ok(verifier.with_service_key(jwk.verifying_key()?)),
+        )
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, RuntimeError> {
+        self.verify_at(token, Utc::now())
+    }
+
+    /// Verifies token as of given point in time.
+    pub fn verify_at(&self, token: &str, now: DateTime<Utc>) -> Result<Claims, RuntimeError> {
+        let token = if self.service_keys.is_empty() {
+            inner_token(token)?
+        } else {
+            verify_countersignature(token, |id| self.service_keys.get(id).copied())?.into()
+        };
+        let claims = verify_signature(&token, &*self.provider.hardware_id()?, self.stretching.as_ref())?;
+
+        let now = now.timestamp();
+        let leeway = self.leeway.as_secs() as i64;
+
+        if claims.expires_at + leeway + self.expiry_grace.as_secs() as i64 <= now {
+            return Err(RuntimeError::TokenExpired);
+        }
+        if claims.issued_at - leeway > now {
+            return Err(RuntimeError::TokenNotYetValid);
+        }
+        if self.issuer.as_ref().is_some_and(|issuer| *issuer != claims.issuer) {
+            return Err(RuntimeError::InvalidIssuer);
+        }
+        if self
+            .audience
+            .as_ref()
+            .is_some_and(|audience| !claims.audience.contains(audience))
+        {
+            return Err(RuntimeError::InvalidAudience);
+        }
+        if self.minion_id.is_some() && self.minion_id != claims.minion_id {
+            return Err(RuntimeError::InvalidMinion);
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::countersign::{countersign, ServiceKey};
+    use crate::kdf::{Kdf, KeyStretching};
+    use crate::model::{Audience, Claims, ClaimsVersion};
+    use crate::runtime_error::RuntimeError;
+    use crate::token::{decode_claims, sign_claims};
+    use crate::verifier::{FileHardwareId, HardwareIdProvider, StaticHardwareId, Verifier};
+    use chrono::{TimeZone, Utc};
+    use ed25519_dalek::SigningKey;
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+    use std::fs::{remove_file, write};
+    use std::rc::Rc;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    const KEY: &str = "qwerta";
+    const ISSUER: &str = "unit-test";
+    const AUDIENCE: &str = "local";
+    const ISSUED_AT: i64 = 1_000_000;
+    const EXPIRES_AT: i64 = 2_000_000;
+
+    fn token() -> String {
+        token_for(AUDIENCE.into(), None)
+    }
+
+    fn token_for(audience: Audience, minion_id: Option<String>) -> String {
+        sign_claims(
+            &Claims {
+                issuer: ISSUER.to_string(),
+                user: "foo:bar".to_string(),
+                minion_id,
+                audience,
+                expires_at: EXPIRES_AT,
+                issued_at: ISSUED_AT,
+                licenses: HashMap::new(),
+                inventory: vec![],
+                version: ClaimsVersion::V1,
+            },
+            &KEY.into(),
+            None,
+        )
+        .unwrap()
+    }
+
+    fn verifier() -> Verifier<StaticHardwareId> {
+        Verifier::new(StaticHardwareId(Rc::new(KEY.into())))
+    }
+
+    #[test]
+    fn verify_valid_token() {
+        let claims = verifier()
+            .with_issuer(ISSUER)
+            .with_audience(AUDIENCE)
+            .verify_at(&token(), Utc.timestamp_opt(ISSUED_AT + 10, 0).unwrap())
+            .unwrap();
+
+        assert_eq!(ISSUER, claims.issuer);
+        assert!(claims.audience.contains(AUDIENCE));
+    }
+
+    #[test]
+    fn verify_other_hardware() {
+        let verifier = Verifier::new(StaticHardwareId(Rc::new("qwertp".into())));
+
+        assert!(matches!(
+            verifier.verify_at(&token(), Utc.timestamp_opt(ISSUED_AT, 0).unwrap()),
+            Err(RuntimeError::JwtError(_))
+        ));
+    }
+
+    #[test]
+    fn verify_expired_token() {
+        assert!(matches!(
+            verifier().verify_at(&token(), Utc.timestamp_opt(EXPIRES_AT, 0).unwrap()),
+            Err(RuntimeError::TokenExpired)
+        ));
+    }
+
+    #[test]
+    fn verify_token_from_future() {
+        assert!(matches!(
+            verifier().verify_at(&token(), Utc.timestamp_opt(ISSUED_AT - 10, 0).unwrap()),
+            Err(RuntimeError::TokenNotYetValid)
+        ));
+    }
+
+    #[test]
+    fn verify_with_leeway() {
+        let verifier = verifier().with_leeway(Duration::from_secs(30));
+
+        assert!(verifier
+            .verify_at(&token(), Utc.timestamp_opt(EXPIRES_AT + 10, 0).unwrap())
+            .is_ok());
+        assert!(verifier
+            .verify_at(&token(), Utc.timestamp_opt(ISSUED_AT - 10, 0).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn verify_with_expiry_grace() {
+        let verifier = verifier().with_expiry_grace(Duration::from_secs(30));
+
+        assert!(verifier
+            .verify_at(&token(), Utc.timestamp_opt(EXPIRES_AT + 10, 0).unwrap())
+            .is_ok());
+        assert!(matches!(
+            verifier.verify_at(&token(), Utc.timestamp_opt(EXPIRES_AT + 30, 0).unwrap()),
+            Err(RuntimeError::TokenExpired)
+        ));
+        // grace doesn't relax issue time
+        assert!(matches!(
+            verifier.verify_at(&token(), Utc.timestamp_opt(ISSUED_AT - 10, 0).unwrap()),
+            Err(RuntimeError::TokenNotYetValid)
+        ));
+    }
+
+    #[test]
+    fn verify_other_issuer() {
+        assert!(matches!(
+            verifier()
+                .with_issuer("other")
+                .verify_at(&token(), Utc.timestamp_opt(ISSUED_AT, 0).unwrap()),
+            Err(RuntimeError::InvalidIssuer)
+        ));
+    }
+
+    #[test]
+    fn verify_other_audience() {
+        assert!(matches!(
+            verifier()
+                .with_audience("other")
+                .verify_at(&token(), Utc.timestamp_opt(ISSUED_AT, 0).unwrap()),
+            Err(RuntimeError::InvalidAudience)
+        ));
+    }
+
+    #[test]
+    fn verify_any_of_multiple_audiences() {
+        let token = token_for(Audience::Multiple(vec!["salt".to_string(), AUDIENCE.to_string()]), None);
+        let now = Utc.timestamp_opt(ISSUED_AT, 0).unwrap();
+
+        assert!(verifier().with_audience(AUDIENCE).verify_at(&token, now).is_ok());
+        assert!(verifier().with_audience("salt").verify_at(&token, now).is_ok());
+        assert!(matches!(
+            verifier().with_audience("other").verify_at(&token, now),
+            Err(RuntimeError::InvalidAudience)
+        ));
+    }
+
+    #[test]
+    fn verify_minion_id() {
+        let bound = token_for(AUDIENCE.into(), Some("node-1".to_string()));
+        let now = Utc.timestamp_opt(ISSUED_AT, 0).unwrap();
+
+        assert!(verifier().with_minion_id("node-1").verify_at(&bound, now).is_ok());
+        assert!(matches!(
+            verifier().with_minion_id("node-2").verify_at(&bound, now),
+            Err(RuntimeError::InvalidMinion)
+        ));
+        assert!(matches!(
+            verifier().with_minion_id("node-1").verify_at(&token(), now),
+            Err(RuntimeError::InvalidMinion)
+        ));
+    }
+
+    #[test]
+    fn verify_stretched_key() {
+        let stretching = KeyStretching {
+            kdf: Kdf::Pbkdf2 { iterations: 10 },
+            bounds: Kdf::Pbkdf2 { iterations: 10 }.into(),
+            pepper: Some(Rc::new("pepper".into())),
+        };
+        let token = sign_claims(&decode_claims(&token()).unwrap(), &KEY.into(), Some(&stretching)).unwrap();
+        let now = Utc.timestamp_opt(ISSUED_AT, 0).unwrap();
+
+        assert!(verifier()
+            .with_key_stretching(stretching.clone())
+            .verify_at(&token, now)
+            .is_ok());
+        assert!(matches!(
+            verifier().verify_at(&token, now),
+            Err(RuntimeError::KdfMismatch)
+        ));
+        assert!(matches!(
+            verifier()
+                .with_key_stretching(KeyStretching {
+                    pepper: None,
+                    ..stretching
+                })
+                .verify_at(&token, now),
+            Err(RuntimeError::JwtError(_))
+        ));
+    }
+
+    #[test]
+    fn verify_countersigned_token() {
+        let service_key = ServiceKey::new(SigningKey::from_bytes(&[1; 32]));
+        let countersigned = countersign(&token(), &service_key).unwrap();
+        let now = Utc.timestamp_opt(ISSUED_AT, 0).unwrap();
+
+        // hardware binding alone is still checked without trusted service keys
+        assert!(verifier().verify_at(&countersigned, now).is_ok());
+        assert!(verifier()
+            .with_service_key(service_key.verifying_key())
+            .verify_at(&countersigned, now)
+            .is_ok());
+        assert!(matches!(
+            verifier()
+                .with_service_key(service_key.verifying_key())
+                .verify_at(&token(), now),
+            Err(RuntimeError::MissingCountersignature)
+        ));
+        assert!(matches!(
+            verifier()
+                .with_service_key(SigningKey::from_bytes(&[2; 32]).verifying_key())
+                .verify_at(&countersigned, now),
+            Err(RuntimeError::UnknownServiceKey(_))
+        ));
+    }
+
+    #[test]
+    fn verify_with_closure_provider() {
+        let verifier = Verifier::new(|| Ok(Rc::new(KEY.into())));
+
+        assert!(verifier
+            .verify_at(&token(), Utc.timestamp_opt(ISSUED_AT, 0).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn read_hardware_id_from_file() {
+        let path = temp_dir().join(format!("ivms-hardware-id-{}", Uuid::new_v4()));
+        write(&path, format!("{KEY}\n")).unwrap();
+
+        let hardware_id = FileHardwareId::new(&path).hardware_id();
+        remove_file(&path).unwrap();
+
+        assert_eq!(KEY, hardware_id.unwrap().expose());
+    }
+
+    #[test]
+    fn read_missing_hardware_id_file() {
+        let provider = FileHardwareId::new(temp_dir().join(format!("ivms-hardware-id-{}", Uuid::new_v4())));
+
+        assert!(matches!(provider.hardware_id(), Err(RuntimeError::HardwareIdError(_))));
+    }
+}
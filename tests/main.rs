@@ -4,14 +4,13 @@
  * @copyright 2023 - 2024 © by Rafał Wrzeszcz - Wrzasq.pl.
  */
 
-#![feature(async_closure, future_join)]
-
 use aws_config::load_defaults;
 use aws_sdk_lambda::Client as LambdaClient;
 use aws_smithy_runtime_api::client::behavior_version::BehaviorVersion;
 use aws_smithy_types::Blob;
 use cucumber::{given, then, when, World};
 use futures::future::join_all;
+use futures::join;
 use hmac::digest::KeyInit;
 use hmac::Hmac;
 use jwt::{Claims, VerifyWithKey};
@@ -19,7 +18,6 @@ use serde_json::{from_slice, json, to_vec};
 use sha2::Sha512;
 use std::collections::HashMap;
 use std::env::{var, VarError};
-use std::future::join;
 use tokio::main as tokio_main;
 
 macro_rules! serialize_blob {
@@ -49,7 +47,7 @@ struct TestWorld {
 
 impl TestWorld {
     async fn new() -> Result<Self, VarError> {
-        let config = &load_defaults(BehaviorVersion::v2023_11_09()).await;
+        let config = &load_defaults(BehaviorVersion::latest()).await;
 
         Ok(Self {
             generator_lambda: var("GENERATOR_LAMBDA")?,
@@ -122,7 +120,7 @@ async fn main() {
                 if let Some(&mut ref cleanup) = world {
                     let inventory_tasks = cleanup.cleanup_inventories.iter().map(|key| async {
                         delete_inventory(
-                            &cleanup,
+                            cleanup,
                             &Some(key.0.clone()),
                             &Some(key.1.clone()),
                             &Some(key.2.clone()),
@@ -133,7 +131,7 @@ async fn main() {
 
                     let license_tasks = cleanup.cleanup_licenses.iter().map(|key| async {
                         delete_license(
-                            &cleanup,
+                            cleanup,
                             &Some(key.0.clone()),
                             &Some(key.1.clone()),
                             &Some(key.2.clone()),
@@ -141,7 +139,7 @@ async fn main() {
                         .await
                     });
 
-                    join!(join_all(inventory_tasks), join_all(license_tasks),).await;
+                    join!(join_all(inventory_tasks), join_all(license_tasks));
                 }
             })
         })